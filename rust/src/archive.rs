@@ -0,0 +1,206 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::postprocess::{fixup_objfile_bytes, global_symbol_names, Target};
+use crate::{Encoding, Function, SymbolVisibility};
+
+const AR_MAGIC: &[u8; 8] = b"!<arch>\n";
+const HEADER_SIZE: usize = 60;
+
+struct ArMember {
+    name: String,
+    data: Vec<u8>,
+}
+
+/// A System V/GNU `ar` archive: the common format produced by build systems
+/// that bundle per-TU objects before linking. Member order and the `//`
+/// long-name table are preserved across a round trip; the `/` symbol index
+/// is always regenerated from the (possibly rewritten) members.
+struct Archive {
+    members: Vec<ArMember>,
+}
+
+impl Archive {
+    fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < AR_MAGIC.len() || &data[..AR_MAGIC.len()] != AR_MAGIC {
+            return Err(anyhow::anyhow!("not an ar archive"));
+        }
+
+        let mut pos = AR_MAGIC.len();
+        let mut long_names: Vec<u8> = vec![];
+        let mut members = vec![];
+
+        while pos + HEADER_SIZE <= data.len() {
+            let header = &data[pos..pos + HEADER_SIZE];
+            let raw_name = std::str::from_utf8(&header[0..16])?.trim_end().to_string();
+            let size: usize = std::str::from_utf8(&header[48..58])?.trim().parse()?;
+            let content_start = pos + HEADER_SIZE;
+            let content = data[content_start..content_start + size].to_vec();
+
+            if raw_name == "//" {
+                long_names = content;
+            } else if raw_name == "/" || raw_name == "__.SYMDEF" {
+                // Symbol index; always regenerated on write.
+            } else if let Some(offset) = raw_name.strip_prefix('/') {
+                let offset: usize = offset.parse()?;
+                let end = long_names[offset..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map(|p| offset + p)
+                    .unwrap_or(long_names.len());
+                let name = String::from_utf8_lossy(&long_names[offset..end])
+                    .trim_end_matches('/')
+                    .to_string();
+                members.push(ArMember { name, data: content });
+            } else {
+                let name = raw_name.trim_end_matches('/').to_string();
+                members.push(ArMember { name, data: content });
+            }
+
+            // Members are padded to an even offset.
+            pos = content_start + size + (size % 2);
+        }
+
+        Ok(Self { members })
+    }
+
+    fn member_header(name: &str, size: usize) -> [u8; HEADER_SIZE] {
+        let mut h = [b' '; HEADER_SIZE];
+        let write_field = |h: &mut [u8; HEADER_SIZE], start: usize, s: &str| {
+            let b = s.as_bytes();
+            h[start..start + b.len()].copy_from_slice(b);
+        };
+        write_field(&mut h, 0, name);
+        write_field(&mut h, 16, "0"); // mtime
+        write_field(&mut h, 28, "0"); // uid
+        write_field(&mut h, 34, "0"); // gid
+        write_field(&mut h, 40, "644"); // mode
+        write_field(&mut h, 48, &size.to_string());
+        h[58] = b'`';
+        h[59] = b'\n';
+        h
+    }
+
+    /// Serialize the archive, regenerating the `/` symbol index member from
+    /// the current global, defined symbols of every member.
+    fn write(&self, target: Target) -> Result<Vec<u8>> {
+        let mut sym_entries: Vec<(usize, Vec<u8>)> = vec![];
+        for (i, m) in self.members.iter().enumerate() {
+            for name in global_symbol_names(&m.data, target).unwrap_or_default() {
+                sym_entries.push((i, name));
+            }
+        }
+
+        let mut long_names = vec![];
+        let mut long_name_offset: HashMap<&str, usize> = HashMap::new();
+        for m in &self.members {
+            if m.name.len() > 15 && !long_name_offset.contains_key(m.name.as_str()) {
+                long_name_offset.insert(&m.name, long_names.len());
+                long_names.extend_from_slice(m.name.as_bytes());
+                long_names.push(b'/');
+                long_names.push(b'\n');
+            }
+        }
+
+        let symtab_strs: Vec<u8> = sym_entries
+            .iter()
+            .flat_map(|(_, name)| name.iter().copied().chain([0]))
+            .collect();
+        let symtab_size = 4 + sym_entries.len() * 4 + symtab_strs.len();
+
+        let mut out = AR_MAGIC.to_vec();
+
+        // The symbol index's entries point at member header offsets that
+        // aren't known until every member has been laid out, so reserve the
+        // space now and patch it in afterwards.
+        out.extend(Self::member_header("/", symtab_size));
+        let offsets_pos = out.len();
+        out.extend(vec![0u8; symtab_size]);
+        if symtab_size % 2 != 0 {
+            out.push(b'\n');
+        }
+
+        if !long_names.is_empty() {
+            out.extend(Self::member_header("//", long_names.len()));
+            out.extend(&long_names);
+            if long_names.len() % 2 != 0 {
+                out.push(b'\n');
+            }
+        }
+
+        let mut member_header_offsets = vec![0usize; self.members.len()];
+        for (i, m) in self.members.iter().enumerate() {
+            member_header_offsets[i] = out.len();
+            let name = if m.name.len() > 15 {
+                format!("/{}", long_name_offset[m.name.as_str()])
+            } else {
+                format!("{}/", m.name)
+            };
+            out.extend(Self::member_header(&name, m.data.len()));
+            out.extend(&m.data);
+            if m.data.len() % 2 != 0 {
+                out.push(b'\n');
+            }
+        }
+
+        let mut body = Vec::with_capacity(symtab_size);
+        body.extend((sym_entries.len() as u32).to_be_bytes());
+        for (member_index, _) in &sym_entries {
+            body.extend((member_header_offsets[*member_index] as u32).to_be_bytes());
+        }
+        body.extend(&symtab_strs);
+        out[offsets_pos..offsets_pos + symtab_size].copy_from_slice(&body);
+
+        Ok(out)
+    }
+}
+
+/// True if `data` looks like an `ar` archive rather than a bare ELF object.
+pub(crate) fn is_archive(data: &[u8]) -> bool {
+    data.len() >= AR_MAGIC.len() && &data[..AR_MAGIC.len()] == AR_MAGIC
+}
+
+/// Run the GLOBAL_ASM fixup pipeline against a single named member of a
+/// static archive, splicing the rewritten object back in and regenerating
+/// the archive's symbol index, so callers don't have to unpack and re-`ar`
+/// archives by hand.
+pub(crate) fn fixup_archive_member(
+    archive_path: &PathBuf,
+    member_name: &str,
+    functions: &[Function],
+    asm_prelude: &str,
+    assembler: &str,
+    output_enc: &Encoding,
+    drop_mdebug_gptab: bool,
+    convert_statics: SymbolVisibility,
+    target: Target,
+    validate: bool,
+) -> Result<()> {
+    let data = fs::read(archive_path)?;
+    let mut archive = Archive::parse(&data)?;
+
+    let member = archive
+        .members
+        .iter_mut()
+        .find(|m| m.name == member_name)
+        .ok_or_else(|| anyhow::anyhow!("no such archive member: {}", member_name))?;
+
+    // Run the merge pipeline directly against the member's bytes, in place,
+    // rather than extracting it to a temp file and re-reading the result.
+    member.data = fixup_objfile_bytes(
+        &member.data,
+        member_name,
+        functions,
+        asm_prelude,
+        assembler,
+        output_enc,
+        drop_mdebug_gptab,
+        convert_statics,
+        target,
+        validate,
+    )?;
+
+    fs::write(archive_path, archive.write(target)?)?;
+    Ok(())
+}
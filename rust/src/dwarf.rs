@@ -0,0 +1,443 @@
+//! Minimal DWARF `.debug_abbrev`/`.debug_info` walker used to recover
+//! file-local static symbols on targets/compilers that emit DWARF instead of
+//! the MIPS ECOFF `.mdebug` symbolic header (e.g. modern GCC, or LE-MIPS
+//! targets). This only decodes the handful of tags/forms needed for that:
+//! it is not a general-purpose DWARF reader.
+
+use std::collections::HashMap;
+
+use binrw::Endian;
+
+const DW_TAG_LEXICAL_BLOCK: u64 = 0x0b;
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_TAG_VARIABLE: u64 = 0x34;
+
+const DW_AT_LOCATION: u64 = 0x02;
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_EXTERNAL: u64 = 0x3f;
+const DW_AT_LOW_PC: u64 = 0x11;
+
+const DW_OP_ADDR: u8 = 0x03;
+
+struct AbbrevAttr {
+    attr: u64,
+    form: u64,
+    /// The attribute's value for DW_FORM_implicit_const (0x21), which is
+    /// baked into the abbrev table itself rather than .debug_info.
+    implicit_const: Option<i64>,
+}
+
+struct AbbrevDecl {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<AbbrevAttr>,
+}
+
+enum AttrValue {
+    Addr(u64),
+    Block(Vec<u8>),
+    Flag(bool),
+    Str(Vec<u8>),
+    Other,
+}
+
+/// A file-local static variable or function recovered from DWARF.
+pub(crate) struct StaticSymbol {
+    pub(crate) name: Vec<u8>,
+    pub(crate) address: u32,
+    pub(crate) is_func: bool,
+    /// DIE nesting depth (counting `DW_TAG_subprogram`/`DW_TAG_lexical_block`
+    /// ancestors) at which this symbol was found. Nonzero means it's an
+    /// in-function static, which may collide in name with others and needs
+    /// disambiguating, just like the `.mdebug` path does.
+    pub(crate) depth: usize,
+}
+
+fn read_uleb128(data: &[u8], pos: &mut usize) -> u64 {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    result
+}
+
+fn read_sleb128(data: &[u8], pos: &mut usize) -> i64 {
+    let mut result = 0i64;
+    let mut shift = 0;
+    let mut byte;
+    loop {
+        byte = data[*pos];
+        *pos += 1;
+        result |= ((byte & 0x7f) as i64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    if shift < 64 && (byte & 0x40) != 0 {
+        result |= -(1i64 << shift);
+    }
+    result
+}
+
+fn read_uint_at(data: &[u8], offset: usize, size: usize, endian: Endian) -> u64 {
+    let bytes = &data[offset..offset + size];
+    let mut buf = [0u8; 8];
+    match endian {
+        Endian::Big => buf[8 - size..].copy_from_slice(bytes),
+        Endian::Little => buf[..size].copy_from_slice(bytes),
+    }
+    match endian {
+        Endian::Big => u64::from_be_bytes(buf),
+        Endian::Little => u64::from_le_bytes(buf),
+    }
+}
+
+fn read_uint(data: &[u8], pos: &mut usize, size: usize, endian: Endian) -> u64 {
+    let v = read_uint_at(data, *pos, size, endian);
+    *pos += size;
+    v
+}
+
+fn lookup_strp(debug_str: Option<&[u8]>, offset: usize) -> Vec<u8> {
+    let Some(debug_str) = debug_str else { return vec![] };
+    if offset >= debug_str.len() {
+        return vec![];
+    }
+    let end = debug_str[offset..]
+        .iter()
+        .position(|&b| b == 0)
+        .map(|p| offset + p)
+        .unwrap_or(debug_str.len());
+    debug_str[offset..end].to_vec()
+}
+
+fn parse_abbrev_table(data: &[u8], start: usize) -> HashMap<u64, AbbrevDecl> {
+    let mut table = HashMap::new();
+    let mut pos = start;
+    while pos < data.len() {
+        let code = read_uleb128(data, &mut pos);
+        if code == 0 {
+            break;
+        }
+        let tag = read_uleb128(data, &mut pos);
+        let has_children = data[pos] != 0;
+        pos += 1;
+        let mut attrs = vec![];
+        loop {
+            let attr = read_uleb128(data, &mut pos);
+            let form = read_uleb128(data, &mut pos);
+            let implicit_const = if form == 0x21 {
+                // DW_FORM_implicit_const: the value lives in the abbrev
+                // table itself, not the .debug_info stream.
+                Some(read_sleb128(data, &mut pos))
+            } else {
+                None
+            };
+            if attr == 0 && form == 0 {
+                break;
+            }
+            attrs.push(AbbrevAttr {
+                attr,
+                form,
+                implicit_const,
+            });
+        }
+        table.insert(code, AbbrevDecl { tag, has_children, attrs });
+    }
+    table
+}
+
+/// Reads one attribute's value and advances `pos` past it. Returns `None` for
+/// a form we don't know how to size, in which case the caller must give up
+/// on the rest of this compilation unit (we'd otherwise desync).
+fn read_form(
+    data: &[u8],
+    pos: &mut usize,
+    form: u64,
+    address_size: u8,
+    debug_str: Option<&[u8]>,
+    endian: Endian,
+    implicit_const: Option<i64>,
+) -> Option<AttrValue> {
+    Some(match form {
+        0x01 => AttrValue::Addr(read_uint(data, pos, address_size as usize, endian)), // addr
+        0x03 => {
+            // block2
+            let len = read_uint(data, pos, 2, endian) as usize;
+            let block = data[*pos..*pos + len].to_vec();
+            *pos += len;
+            AttrValue::Block(block)
+        }
+        0x04 => {
+            // block4
+            let len = read_uint(data, pos, 4, endian) as usize;
+            let block = data[*pos..*pos + len].to_vec();
+            *pos += len;
+            AttrValue::Block(block)
+        }
+        0x05 | 0x06 | 0x07 => {
+            // data2 / data4 / data8
+            let size = match form {
+                0x05 => 2,
+                0x06 => 4,
+                _ => 8,
+            };
+            *pos += size;
+            AttrValue::Other
+        }
+        0x08 => {
+            // string
+            let end = data[*pos..]
+                .iter()
+                .position(|&b| b == 0)
+                .map(|p| *pos + p)?;
+            let s = data[*pos..end].to_vec();
+            *pos = end + 1;
+            AttrValue::Str(s)
+        }
+        0x09 => {
+            // block
+            let len = read_uleb128(data, pos) as usize;
+            let block = data[*pos..*pos + len].to_vec();
+            *pos += len;
+            AttrValue::Block(block)
+        }
+        0x0a => {
+            // block1
+            let len = data[*pos] as usize;
+            *pos += 1;
+            let block = data[*pos..*pos + len].to_vec();
+            *pos += len;
+            AttrValue::Block(block)
+        }
+        0x0b | 0x11 | 0x25 | 0x29 => {
+            // data1 / ref1 / strx1 / addrx1
+            *pos += 1;
+            AttrValue::Other
+        }
+        0x0c => {
+            // flag
+            let v = data[*pos] != 0;
+            *pos += 1;
+            AttrValue::Flag(v)
+        }
+        0x0d => {
+            // sdata
+            read_sleb128(data, pos);
+            AttrValue::Other
+        }
+        0x0e => {
+            // strp
+            let offset = read_uint(data, pos, 4, endian) as usize;
+            AttrValue::Str(lookup_strp(debug_str, offset))
+        }
+        0x0f | 0x15 | 0x1a | 0x1b | 0x22 | 0x23 => {
+            // udata / ref_udata / strx / addrx / loclistx / rnglistx
+            read_uleb128(data, pos);
+            AttrValue::Other
+        }
+        0x10 | 0x17 | 0x1f => {
+            // ref_addr / sec_offset / line_strp (4 bytes; DWARF32)
+            *pos += 4;
+            AttrValue::Other
+        }
+        0x12 | 0x26 | 0x2a => {
+            // ref2 / strx2 / addrx2
+            *pos += 2;
+            AttrValue::Other
+        }
+        0x13 | 0x1c => {
+            // ref4 / ref_sup4
+            *pos += 4;
+            AttrValue::Other
+        }
+        0x27 | 0x2b => {
+            // strx3 / addrx3
+            *pos += 3;
+            AttrValue::Other
+        }
+        0x28 | 0x2c => {
+            // strx4 / addrx4
+            *pos += 4;
+            AttrValue::Other
+        }
+        0x14 | 0x20 | 0x24 => {
+            // ref8 / ref_sig8 / ref_sup8
+            *pos += 8;
+            AttrValue::Other
+        }
+        0x18 => {
+            // exprloc
+            let len = read_uleb128(data, pos) as usize;
+            let block = data[*pos..*pos + len].to_vec();
+            *pos += len;
+            AttrValue::Block(block)
+        }
+        0x19 => AttrValue::Flag(true), // flag_present
+        0x1e => {
+            // data16
+            *pos += 16;
+            AttrValue::Other
+        }
+        0x16 => {
+            // indirect: actual form follows as a uleb
+            let real_form = read_uleb128(data, pos);
+            return read_form(
+                data,
+                pos,
+                real_form,
+                address_size,
+                debug_str,
+                endian,
+                implicit_const,
+            );
+        }
+        0x21 => {
+            // implicit_const: the value was already parsed out of the abbrev
+            // table, so this consumes nothing from .debug_info. Modern
+            // GCC/Clang use it for simple boolean attributes like
+            // DW_AT_external/DW_AT_declaration.
+            AttrValue::Flag(implicit_const.unwrap_or(0) != 0)
+        }
+        _ => return None,
+    })
+}
+
+/// Walk every compilation unit in `.debug_info`, returning the file-local
+/// static variables and functions found. Best-effort: a CU we can't parse
+/// (an unsupported form, a truncated section, ...) is simply skipped rather
+/// than treated as fatal, since this is an opt-in fallback for when
+/// `.mdebug` isn't available.
+pub(crate) fn find_static_symbols(
+    debug_info: &[u8],
+    debug_abbrev: &[u8],
+    debug_str: Option<&[u8]>,
+    endian: Endian,
+) -> Vec<StaticSymbol> {
+    let mut out = vec![];
+    let mut cu_pos = 0;
+
+    while cu_pos + 11 <= debug_info.len() {
+        let unit_length = read_uint_at(debug_info, cu_pos, 4, endian) as usize;
+        let cu_end = cu_pos + 4 + unit_length;
+        if unit_length == 0 || cu_end > debug_info.len() {
+            break;
+        }
+        let version = read_uint_at(debug_info, cu_pos + 4, 2, endian) as u16;
+
+        let (abbrev_offset, address_size, header_size) = if version >= 5 {
+            let address_size = debug_info[cu_pos + 7];
+            let abbrev_offset = read_uint_at(debug_info, cu_pos + 8, 4, endian) as usize;
+            (abbrev_offset, address_size, 12)
+        } else {
+            let abbrev_offset = read_uint_at(debug_info, cu_pos + 6, 4, endian) as usize;
+            let address_size = debug_info[cu_pos + 10];
+            (abbrev_offset, address_size, 11)
+        };
+
+        let abbrevs = parse_abbrev_table(debug_abbrev, abbrev_offset);
+
+        let mut pos = cu_pos + header_size;
+        let mut depth = 0usize;
+        let mut scope_stack: Vec<bool> = vec![];
+
+        'cu: while pos < cu_end {
+            let code = read_uleb128(debug_info, &mut pos);
+            if code == 0 {
+                if let Some(counted) = scope_stack.pop() {
+                    if counted {
+                        depth = depth.saturating_sub(1);
+                    }
+                }
+                continue;
+            }
+            let Some(decl) = abbrevs.get(&code) else {
+                break 'cu;
+            };
+
+            let mut name = None;
+            let mut low_pc = None;
+            let mut location_addr = None;
+            let mut external = false;
+
+            for a in &decl.attrs {
+                let Some(value) = read_form(
+                    debug_info,
+                    &mut pos,
+                    a.form,
+                    address_size,
+                    debug_str,
+                    endian,
+                    a.implicit_const,
+                ) else {
+                    break 'cu;
+                };
+                match a.attr {
+                    DW_AT_NAME => {
+                        if let AttrValue::Str(s) = value {
+                            name = Some(s);
+                        }
+                    }
+                    DW_AT_LOW_PC => {
+                        if let AttrValue::Addr(v) = value {
+                            low_pc = Some(v);
+                        }
+                    }
+                    DW_AT_LOCATION => {
+                        if let AttrValue::Block(b) = value {
+                            if b.len() == 5 && b[0] == DW_OP_ADDR {
+                                location_addr = Some(read_uint_at(&b, 1, 4, endian));
+                            }
+                        }
+                    }
+                    DW_AT_EXTERNAL => {
+                        if let AttrValue::Flag(f) = value {
+                            external = f;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if decl.tag == DW_TAG_VARIABLE && !external {
+                if let (Some(name), Some(address)) = (name, location_addr) {
+                    out.push(StaticSymbol {
+                        name,
+                        address: address as u32,
+                        is_func: false,
+                        depth,
+                    });
+                }
+            } else if decl.tag == DW_TAG_SUBPROGRAM && !external {
+                if let (Some(name), Some(address)) = (name, low_pc) {
+                    out.push(StaticSymbol {
+                        name,
+                        address: address as u32,
+                        is_func: true,
+                        depth,
+                    });
+                }
+            }
+
+            let counts = decl.tag == DW_TAG_SUBPROGRAM || decl.tag == DW_TAG_LEXICAL_BLOCK;
+            if decl.has_children {
+                if counts {
+                    depth += 1;
+                }
+                scope_stack.push(counts);
+            }
+        }
+
+        cu_pos = cu_end;
+    }
+
+    out
+}
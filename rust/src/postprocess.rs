@@ -4,14 +4,15 @@ use std::{
     cmp::Ordering,
     collections::{HashMap, HashSet},
     fs::{self, File},
-    io::{BufWriter, Cursor, Seek, SeekFrom, Write},
-    path::PathBuf,
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
     process::Command,
     rc::Rc,
 };
 
 use binrw::{binrw, BinRead, BinResult, BinWrite, Endian};
 use enum_map::EnumMap;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use temp_dir::TempDir;
 
 use crate::{Encoding, Function, OutputSection, SymbolVisibility};
@@ -22,7 +23,9 @@ const EI_DATA: usize = 5;
 
 const SHN_UNDEF: usize = 0;
 const SHN_ABS: usize = 0xfff1;
+const SHN_COMMON: usize = 0xfff2;
 const SHN_XINDEX: usize = 0xffff;
+const SHN_LORESERVE: usize = 0xff00;
 
 const STT_OBJECT: u8 = 1;
 const STT_FUNC: u8 = 2;
@@ -38,10 +41,18 @@ const SHT_STRTAB: u32 = 3;
 const SHT_RELA: u32 = 4;
 const SHT_NOBITS: u32 = 8;
 const SHT_REL: u32 = 9;
+const SHT_SYMTAB_SHNDX: u32 = 18;
 const SHT_MIPS_GPTAB: u32 = 0x70000003;
 const SHT_MIPS_DEBUG: u32 = 0x70000005;
 
 const SHF_LINK_ORDER: u32 = 0x80;
+const SHF_COMPRESSED: u32 = 0x800;
+
+const ELFCOMPRESS_ZLIB: u32 = 1;
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+const R_MIPS_HI16: u32 = 5;
+const R_MIPS_LO16: u32 = 6;
 
 const MIPS_DEBUG_ST_STATIC: usize = 2;
 const MIPS_DEBUG_ST_PROC: usize = 6;
@@ -53,6 +64,38 @@ const MIPS_DEBUG_ST_STRUCT: usize = 26;
 const MIPS_DEBUG_ST_UNION: usize = 27;
 const MIPS_DEBUG_ST_ENUM: usize = 28;
 
+/// The architecture of the objfile being patched. This lets the ELF layer
+/// serve non-MIPS decomp toolchains (e.g. PPC/GameCube) without forking the
+/// MIPS-specific fixup logic.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Target {
+    Mips,
+    Ppc,
+}
+
+impl Target {
+    const EM_MIPS: u16 = 8;
+    const EM_PPC: u16 = 20;
+
+    fn e_machine(&self) -> u16 {
+        match self {
+            Target::Mips => Self::EM_MIPS,
+            Target::Ppc => Self::EM_PPC,
+        }
+    }
+
+    /// An assembly line that pads a text-section gap with a single 4-byte nop.
+    fn nop_line(&self) -> String {
+        match self {
+            Target::Mips => "nop".to_owned(),
+            // PPC's nop mnemonic assembles to the same bytes, but we spell it
+            // out explicitly so the gap is unambiguous regardless of the
+            // configured assembler's pseudo-op support.
+            Target::Ppc => ".4byte 0x60000000".to_owned(),
+        }
+    }
+}
+
 #[binrw]
 struct ElfHeader {
     e_ident: [u8; EI_NIDENT],
@@ -74,14 +117,18 @@ struct ElfHeader {
 impl ElfHeader {
     const SIZE: usize = 52;
 
-    fn new(data: &[u8], endian: Endian) -> BinResult<Self> {
+    fn new(data: &[u8], endian: Endian, target: Target) -> BinResult<Self> {
         let mut cursor = Cursor::new(data);
 
         let header = Self::read_options(&mut cursor, endian, ())?;
 
         assert_eq!(header.e_ident[EI_CLASS], 1, "ELF must be 32-bit");
         assert_eq!(header.e_type, 1, "ELF must be relocatable");
-        assert_eq!(header.e_machine, 8, "ELF must be MIPS 1");
+        assert_eq!(
+            header.e_machine,
+            target.e_machine(),
+            "ELF machine type does not match the configured target"
+        );
         assert_eq!(header.e_phoff, 0, "ELF must not have program headers");
         assert_ne!(header.e_shoff, 0, "ELF must have section headers");
         assert_ne!(
@@ -128,9 +175,9 @@ impl Symbol {
         let mut cursor = Cursor::new(data);
 
         let data = SymbolData::read_options(&mut cursor, endian, ())?;
-        if data.st_shndx == SHN_XINDEX as u16 {
-            panic!("too many sections (SHN_XINDEX not supported)");
-        }
+        // A st_shndx of SHN_XINDEX means the real section index doesn't fit in
+        // 16 bits and is instead stored in the companion SHT_SYMTAB_SHNDX
+        // section; ElfFile::new patches st_shndx with the real value afterwards.
         let st_type = data.st_info & 0xf;
         let st_bind = data.st_info >> 4;
         let st_visibility = data.st_other & 0x3;
@@ -148,19 +195,33 @@ impl Symbol {
         })
     }
 
-    fn to_bin(&self) -> Vec<u8> {
+    /// True if this symbol's real section index doesn't fit in the 16-bit
+    /// `st_shndx` field and must be stored via SHN_XINDEX instead. Reserved
+    /// indices like SHN_ABS and SHN_COMMON fall in the same numeric range but
+    /// must never be extended-indexed.
+    fn needs_xindex(&self) -> bool {
+        self.st_shndx >= SHN_LORESERVE && self.st_shndx != SHN_ABS && self.st_shndx != SHN_COMMON
+    }
+
+    fn to_bin(&self, endian: Endian) -> Vec<u8> {
         let mut rv = vec![];
         let mut cursor = Cursor::new(&mut rv);
 
+        let st_shndx = if self.needs_xindex() {
+            SHN_XINDEX as u16
+        } else {
+            self.st_shndx as u16
+        };
+
         SymbolData {
             st_name: self.st_name as u32,
             st_value: self.st_value as u32,
             st_size: self.st_size as u32,
             st_info: self.st_bind << 4 | self.st_type,
             st_other: self.st_visibility,
-            st_shndx: self.st_shndx as u16,
+            st_shndx,
         }
-        .write_options(&mut cursor, Endian::Big, ())
+        .write_options(&mut cursor, endian, ())
         .unwrap();
         rv
     }
@@ -244,6 +305,17 @@ impl Hdrr {
     const SIZE: usize = 96;
 }
 
+#[binrw]
+struct Chdr {
+    ch_type: u32,
+    ch_size: u32,
+    ch_addralign: u32,
+}
+
+impl Chdr {
+    const SIZE: usize = 12;
+}
+
 #[binrw]
 #[derive(Clone)]
 struct SectionHeader {
@@ -272,24 +344,50 @@ struct Section {
     symbol_entries: Vec<Rc<RefCell<Symbol>>>,
     relocations: Vec<Relocation>,
     name: String,
+    /// Set to the original `ch_type` when this section was read with
+    /// SHF_COMPRESSED set; `data` holds the decompressed contents, and the
+    /// flag is cleared on `header` so the rest of the pipeline can treat
+    /// `data` as opaque. The section is recompressed on write.
+    compression: Option<u32>,
 }
 
 impl Section {
     fn new(data: &[u8], other_data: &[u8], index: usize, endian: Endian) -> BinResult<Self> {
         let mut cursor = Cursor::new(data);
 
-        let header = SectionHeader::read_options(&mut cursor, endian, ())?;
+        let mut header = SectionHeader::read_options(&mut cursor, endian, ())?;
         assert!(header.sh_flags & SHF_LINK_ORDER == 0);
         if header.sh_entsize != 0 {
             assert_eq!(header.sh_size % header.sh_entsize, 0);
         }
 
-        let data = if header.sh_type == SHT_NOBITS {
+        let raw_data = if header.sh_type == SHT_NOBITS {
             vec![]
         } else {
             other_data[header.sh_offset as usize..(header.sh_offset + header.sh_size) as usize]
                 .to_vec()
         };
+
+        let (data, compression) = if header.sh_flags & SHF_COMPRESSED != 0 {
+            let chdr = Chdr::read_options(&mut Cursor::new(&raw_data[..Chdr::SIZE]), endian, ())?;
+            let compressed = &raw_data[Chdr::SIZE..];
+            let decompressed = match chdr.ch_type {
+                ELFCOMPRESS_ZLIB => {
+                    let mut out = Vec::with_capacity(chdr.ch_size as usize);
+                    ZlibDecoder::new(compressed).read_to_end(&mut out).unwrap();
+                    out
+                }
+                ELFCOMPRESS_ZSTD => {
+                    zstd::stream::decode_all(compressed).expect("failed to decompress zstd section")
+                }
+                other => panic!("unsupported SHF_COMPRESSED ch_type: {}", other),
+            };
+            header.sh_flags &= !SHF_COMPRESSED;
+            (decompressed, Some(chdr.ch_type))
+        } else {
+            (raw_data, None)
+        };
+
         Ok(Self {
             header,
             data,
@@ -298,6 +396,7 @@ impl Section {
             symbol_entries: vec![],
             relocations: vec![],
             name: "".into(),
+            compression,
         })
     }
 
@@ -348,11 +447,43 @@ impl Section {
         self.header.sh_type == SHT_REL || self.header.sh_type == SHT_RELA
     }
 
-    fn header_to_bin(&mut self, endian: Endian) -> [u8; SectionHeader::SIZE] {
-        if self.header.sh_type != SHT_NOBITS {
+    /// Produce the bytes that should be written out for this section's data,
+    /// recompressing (and updating `sh_size`/`sh_flags`) if it was originally
+    /// SHF_COMPRESSED.
+    fn data_to_write(&mut self, endian: Endian) -> Vec<u8> {
+        let Some(ch_type) = self.compression else {
             self.header.sh_size = self.data.len() as u32;
-        }
+            return self.data.clone();
+        };
+
+        let compressed = match ch_type {
+            ELFCOMPRESS_ZLIB => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&self.data).unwrap();
+                encoder.finish().unwrap()
+            }
+            ELFCOMPRESS_ZSTD => {
+                zstd::stream::encode_all(self.data.as_slice(), 0).expect("failed to compress zstd section")
+            }
+            other => panic!("unsupported SHF_COMPRESSED ch_type: {}", other),
+        };
 
+        let chdr = Chdr {
+            ch_type,
+            ch_size: self.data.len() as u32,
+            ch_addralign: self.header.sh_addralign,
+        };
+        let mut rv = vec![0; Chdr::SIZE];
+        chdr.write_options(&mut Cursor::new(rv.as_mut_slice()), endian, ())
+            .unwrap();
+        rv.extend(compressed);
+
+        self.header.sh_flags |= SHF_COMPRESSED;
+        self.header.sh_size = rv.len() as u32;
+        rv
+    }
+
+    fn header_to_bin(&mut self, endian: Endian) -> [u8; SectionHeader::SIZE] {
         let mut rv = [0; SectionHeader::SIZE];
         let mut cursor = Cursor::new(rv.as_mut_slice());
 
@@ -442,10 +573,12 @@ impl Section {
 struct ElfFile {
     data: Vec<u8>,
     endian: Endian,
+    target: Target,
     header: ElfHeader,
     sections: Vec<Section>,
     symtab: usize,
     sym_strtab: usize,
+    sym_shndx: Option<usize>,
 }
 
 struct HeaderFields {
@@ -458,7 +591,7 @@ struct HeaderFields {
 }
 
 impl ElfFile {
-    fn new(data: &[u8]) -> BinResult<Self> {
+    fn new(data: &[u8], target: Target) -> BinResult<Self> {
         let data = data.to_vec();
         assert_eq!(data[..4], [0x7f, b'E', b'L', b'F']);
 
@@ -469,7 +602,7 @@ impl ElfFile {
         } else {
             panic!("Invalid ELF endianness");
         };
-        let header = ElfHeader::new(&data[..ElfHeader::SIZE], endian).unwrap();
+        let header = ElfHeader::new(&data[..ElfHeader::SIZE], endian, target).unwrap();
         let offset = header.e_shoff as usize;
         let size = header.e_shentsize as usize;
         let null_section = Section::new(&data[offset..offset + size], &data, 0, endian).unwrap();
@@ -491,6 +624,9 @@ impl ElfFile {
             .position(|s| s.header.sh_type == SHT_SYMTAB)
             .unwrap();
         let sym_strtab_index = sections[symtab_index].header.sh_link as usize;
+        let sym_shndx_index = sections.iter().position(|s| {
+            s.header.sh_type == SHT_SYMTAB_SHNDX && s.header.sh_link as usize == symtab_index
+        });
 
         let shstr = sections[header.e_shstrndx as usize].clone();
         let sym_strtab = sections[sym_strtab_index].clone();
@@ -508,13 +644,30 @@ impl ElfFile {
             }
         }
 
+        if let Some(sym_shndx_index) = sym_shndx_index {
+            let shndx_data = sections[sym_shndx_index].data.clone();
+            for (i, symbol) in sections[symtab_index].symbol_entries.iter().enumerate() {
+                let mut symbol = symbol.borrow_mut();
+                if symbol.st_shndx == SHN_XINDEX {
+                    let bytes: [u8; 4] = shndx_data[i * 4..i * 4 + 4].try_into().unwrap();
+                    let real_index = match endian {
+                        Endian::Big => u32::from_be_bytes(bytes),
+                        Endian::Little => u32::from_le_bytes(bytes),
+                    };
+                    symbol.st_shndx = real_index as usize;
+                }
+            }
+        }
+
         Ok(ElfFile {
             data,
             endian,
+            target,
             header,
             sections,
             symtab: symtab_index,
             sym_strtab: sym_strtab_index,
+            sym_shndx: sym_shndx_index,
         })
     }
 
@@ -542,28 +695,135 @@ impl ElfFile {
         &mut self.sections[self.sym_strtab]
     }
 
-    fn add_section(&mut self, name: &str, fields: &HeaderFields, data: &[u8], endian: Endian) {
+    /// Remap every cross-reference to a section index (symbol `st_shndx`,
+    /// relocation section `sh_link`/`sh_info`, the symtab's string table
+    /// link, `e_shstrndx`, and the `relocated_by` back-pointers) through an
+    /// old-index -> new-index table. Indices not present in `remap` are left
+    /// unchanged.
+    fn apply_index_remap(&mut self, remap: &HashMap<usize, usize>) {
+        let remap_idx = |i: usize| remap.get(&i).copied().unwrap_or(i);
+
+        for sym in self.sections[self.symtab].symbol_entries.clone() {
+            let mut sym = sym.borrow_mut();
+            if sym.st_shndx < SHN_LORESERVE {
+                sym.st_shndx = remap_idx(sym.st_shndx);
+            }
+        }
+
+        for s in &mut self.sections {
+            s.relocated_by = s.relocated_by.iter().map(|&i| remap_idx(i)).collect();
+            if s.is_rel() || s.header.sh_type == SHT_SYMTAB || s.header.sh_type == SHT_SYMTAB_SHNDX
+            {
+                s.header.sh_link = remap_idx(s.header.sh_link as usize) as u32;
+            }
+            if s.is_rel() {
+                s.header.sh_info = remap_idx(s.header.sh_info as usize) as u32;
+            }
+        }
+
+        self.symtab = remap_idx(self.symtab);
+        self.sym_strtab = remap_idx(self.sym_strtab);
+        self.sym_shndx = self.sym_shndx.map(remap_idx);
+        self.header.e_shstrndx = remap_idx(self.header.e_shstrndx as usize) as u16;
+    }
+
+    /// Renumber every section's `index` field to match its current position.
+    fn renumber_sections(&mut self) {
+        for (i, s) in self.sections.iter_mut().enumerate() {
+            s.index = i;
+        }
+    }
+
+    /// Insert a freshly built section at an arbitrary position, shifting and
+    /// fixing up every cross-reference to sections at or after `pos`.
+    fn insert_section_at(
+        &mut self,
+        pos: usize,
+        name: &str,
+        fields: &HeaderFields,
+        data: &[u8],
+        endian: Endian,
+    ) {
+        assert!(pos >= 1 && pos <= self.sections.len());
         let shstr = self
             .sections
             .get_mut(self.header.e_shstrndx as usize)
             .unwrap();
         let sh_name = shstr.add_str(name.as_bytes());
-        let mut s = Section::from_parts(sh_name, fields, data, self.sections.len(), endian);
+
+        let remap: HashMap<usize, usize> = (pos..self.sections.len()).map(|i| (i, i + 1)).collect();
+        self.apply_index_remap(&remap);
+
+        let mut s = Section::from_parts(sh_name, fields, data, pos, endian);
         s.name = name.to_string();
-        self.sections.push(s);
+        self.sections.insert(pos, s);
+        self.renumber_sections();
+    }
+
+    /// Remove the section at `pos` and any `.rel`/`.rela` section that
+    /// relocates it, fixing up every cross-reference: indices above a
+    /// removed position shift down, and surviving indices are renumbered
+    /// accordingly. A GAS/IDO object carries a local STT_SECTION symbol for
+    /// every section, so symbols that pointed at a removed section are
+    /// dropped outright rather than rewritten to SHN_UNDEF -- a STB_LOCAL
+    /// symbol with SHN_UNDEF is treated as an undefined local symbol and
+    /// rejected later in the merge.
+    fn remove_section_at(&mut self, pos: usize) -> Section {
+        assert_ne!(pos, 0, "cannot remove the null section");
+
+        let mut to_remove = self.sections[pos].relocated_by.clone();
+        to_remove.push(pos);
+        to_remove.sort_unstable();
+        to_remove.dedup();
+        let removed_set: HashSet<usize> = to_remove.iter().copied().collect();
+
+        self.sections[self.symtab]
+            .symbol_entries
+            .retain(|sym| !removed_set.contains(&sym.borrow().st_shndx));
+
+        let mut remap: HashMap<usize, usize> = HashMap::new();
+        let mut next = 0;
+        for i in 0..self.sections.len() {
+            if removed_set.contains(&i) {
+                continue;
+            }
+            remap.insert(i, next);
+            next += 1;
+        }
+        self.apply_index_remap(&remap);
+
+        let mut removed = None;
+        for &i in to_remove.iter().rev() {
+            let s = self.sections.remove(i);
+            if i == pos {
+                removed = Some(s);
+            }
+        }
+        self.renumber_sections();
+        removed.unwrap()
+    }
+
+    fn add_section(&mut self, name: &str, fields: &HeaderFields, data: &[u8], endian: Endian) {
+        let pos = self.sections.len();
+        self.insert_section_at(pos, name, fields, data, endian);
     }
 
     fn drop_mdebug_gptab(&mut self) {
-        // We can only drop sections at the end, since otherwise section
-        // references might be wrong. Luckily, these sections typically are.
-        while self.sections.last().unwrap().header.sh_type == SHT_MIPS_DEBUG
-            || self.sections.last().unwrap().header.sh_type == SHT_MIPS_GPTAB
-        {
-            self.sections.pop();
+        // Sections can now be dropped from anywhere: apply_index_remap keeps
+        // every cross-reference (symbol st_shndx, reloc sh_link/sh_info,
+        // e_shstrndx, ...) consistent as indices shift.
+        let to_drop: Vec<usize> = self
+            .sections
+            .iter()
+            .filter(|s| s.header.sh_type == SHT_MIPS_DEBUG || s.header.sh_type == SHT_MIPS_GPTAB)
+            .map(|s| s.index)
+            .collect();
+        for idx in to_drop.into_iter().rev() {
+            self.remove_section_at(idx);
         }
     }
 
-    fn pad_out(writer: &mut BufWriter<&mut File>, align: usize) {
+    fn pad_out<W: Write + Seek>(writer: &mut W, align: usize) {
         let pos = writer.stream_position().unwrap() as usize;
 
         if align > 0 && pos % align != 0 {
@@ -574,7 +834,30 @@ impl ElfFile {
         }
     }
 
-    fn write(&mut self, writer: &mut BufWriter<&mut File>) -> BinResult<()> {
+    /// Regenerate the SHT_SYMTAB_SHNDX section (if one exists) from the
+    /// symtab's current symbol entries, in case any now need an extended
+    /// index (or no longer do).
+    fn sync_symtab_shndx(&mut self) {
+        let Some(sym_shndx) = self.sym_shndx else {
+            return;
+        };
+        let symbols = self.sections[self.symtab].symbol_entries.clone();
+        let mut data = vec![0u8; symbols.len() * 4];
+        for (i, symbol) in symbols.iter().enumerate() {
+            let symbol = symbol.borrow();
+            if symbol.needs_xindex() {
+                let bytes = match self.endian {
+                    Endian::Big => (symbol.st_shndx as u32).to_be_bytes(),
+                    Endian::Little => (symbol.st_shndx as u32).to_le_bytes(),
+                };
+                data[i * 4..i * 4 + 4].copy_from_slice(&bytes);
+            }
+        }
+        self.sections[sym_shndx].data = data;
+    }
+
+    fn write<W: Write + Seek>(&mut self, writer: &mut W) -> BinResult<()> {
+        self.sync_symtab_shndx();
         self.header.e_shnum = self.sections.len() as u16;
         writer.write_all(&self.header.to_bin(self.endian)).unwrap();
 
@@ -587,7 +870,8 @@ impl ElfFile {
                     // The .mdebug section has moved, relocate offsets
                     s.relocate_mdebug(old_offset, self.endian)?;
                 }
-                writer.write_all(&s.data).unwrap();
+                let data = s.data_to_write(self.endian);
+                writer.write_all(&data).unwrap();
             }
         }
 
@@ -605,6 +889,234 @@ impl ElfFile {
     }
 }
 
+/// Fixes up a `R_MIPS_HI16`/`R_MIPS_LO16` pair (`.rel`-style, no explicit
+/// addend) that together reference an offset into `.late_rodata` which has
+/// since moved. `hi_offset`/`lo_offset` are offsets of the instruction words
+/// (already containing the *old* split value) within `section`.
+fn fixup_late_rodata_hi16_lo16(
+    section: &mut Section,
+    hi_offset: usize,
+    lo_offset: usize,
+    moved_late_rodata: &HashMap<usize, usize>,
+    endian: Endian,
+) -> Result<()> {
+    let read_word = |data: &[u8], off: usize| -> u32 {
+        let bytes: [u8; 4] = data[off..off + 4].try_into().unwrap();
+        match endian {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        }
+    };
+    let write_word = |data: &mut [u8], off: usize, word: u32| {
+        let bytes = match endian {
+            Endian::Big => word.to_be_bytes(),
+            Endian::Little => word.to_le_bytes(),
+        };
+        data[off..off + 4].copy_from_slice(&bytes);
+    };
+
+    let hi_word = read_word(&section.data, hi_offset);
+    let lo_word = read_word(&section.data, lo_offset);
+    let hi_imm = (hi_word & 0xffff) as u32;
+    let lo_imm = (lo_word & 0xffff) as i16;
+    let old_value = (hi_imm << 16).wrapping_add(lo_imm as i32 as u32) as usize;
+
+    let new_value = *moved_late_rodata.get(&old_value).ok_or_else(|| {
+        anyhow::anyhow!(
+            "could not fix up hi16/lo16 reference to .late_rodata+{:#x}",
+            old_value
+        )
+    })?;
+
+    let new_lo = (new_value as u32) & 0xffff;
+    let carry = if new_lo & 0x8000 != 0 { 1 } else { 0 };
+    let new_hi = ((new_value as u32) >> 16).wrapping_add(carry) & 0xffff;
+
+    write_word(&mut section.data, hi_offset, (hi_word & 0xffff0000) | new_hi);
+    write_word(&mut section.data, lo_offset, (lo_word & 0xffff0000) | new_lo);
+    Ok(())
+}
+
+/// A file-local static variable or function recovered from a MIPS ECOFF
+/// `.mdebug` symbolic header.
+struct MdebugStatic {
+    name: Vec<u8>,
+    value: usize,
+    /// MIPS_DEBUG_SC_* storage class (1 = text, 2 = data, 3 = bss, 15 = rodata).
+    sc: usize,
+    /// Whether this was found nested inside a procedure/block, meaning its
+    /// name may collide with other locals and needs disambiguating.
+    in_function: bool,
+}
+
+/// Walks every file descriptor/symbol record in a `.mdebug` symbolic header,
+/// returning the `MIPS_DEBUG_ST_STATIC`/`MIPS_DEBUG_ST_STATIC_PROC` entries.
+/// `file_data` is the full object file (field descriptors, symbol records and
+/// the string table are all addressed by absolute file offset), and
+/// `mdebug_data` is just the `.mdebug` section's own contents.
+fn read_mdebug_statics(file_data: &[u8], mdebug_data: &[u8], endian: Endian) -> Vec<MdebugStatic> {
+    let read_u32 = |data: &[u8], offset: usize| -> usize {
+        let bytes: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+        (match endian {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        }) as usize
+    };
+
+    let ifd_max = read_u32(mdebug_data, 18 * 4);
+    let cb_fd_offset = read_u32(mdebug_data, 19 * 4);
+    let cb_sym_offset = read_u32(mdebug_data, 9 * 4);
+    let cb_ss_offset = read_u32(mdebug_data, 15 * 4);
+
+    let mut out = vec![];
+    for i in 0..ifd_max {
+        let offset = cb_fd_offset + 18 * 4 * i;
+        let iss_base = read_u32(file_data, offset + 2 * 4);
+        let isym_base = read_u32(file_data, offset + 4 * 4);
+        let csym = read_u32(file_data, offset + 5 * 4);
+        let mut scope_level = 0i32;
+
+        for j in 0..csym {
+            let offset2 = cb_sym_offset + 12 * (isym_base + j);
+            let iss = read_u32(file_data, offset2);
+            let value = read_u32(file_data, offset2 + 4);
+            let st_sc_index = read_u32(file_data, offset2 + 8);
+            let st = st_sc_index >> 26;
+            let sc = (st_sc_index >> 21) & 0x1F;
+
+            if st == MIPS_DEBUG_ST_STATIC || st == MIPS_DEBUG_ST_STATIC_PROC {
+                let symbol_name_offset = cb_ss_offset + iss_base + iss;
+                let symbol_name_offset_end = file_data
+                    .iter()
+                    .skip(symbol_name_offset)
+                    .position(|x| *x == 0)
+                    .unwrap()
+                    + symbol_name_offset;
+                out.push(MdebugStatic {
+                    name: file_data[symbol_name_offset..symbol_name_offset_end].to_owned(),
+                    value,
+                    sc,
+                    in_function: scope_level > 1,
+                });
+            }
+            match st {
+                MIPS_DEBUG_ST_FILE
+                | MIPS_DEBUG_ST_STRUCT
+                | MIPS_DEBUG_ST_UNION
+                | MIPS_DEBUG_ST_ENUM
+                | MIPS_DEBUG_ST_BLOCK
+                | MIPS_DEBUG_ST_PROC
+                | MIPS_DEBUG_ST_STATIC_PROC => {
+                    scope_level += 1;
+                }
+                MIPS_DEBUG_ST_END => {
+                    scope_level -= 1;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(scope_level, 0);
+    }
+    out
+}
+
+/// Opt-in (`--validate`) consistency check over a freshly-merged object,
+/// run just before it's written out. Catches invariants the merge code
+/// above otherwise assumes silently, so a bad merge shows up as a readable
+/// report instead of a corrupt object that only fails at link or run time.
+fn validate_objfile(objfile: &ElfFile) -> Vec<String> {
+    let mut issues = vec![];
+
+    let symtab = objfile.symtab();
+    let num_syms = symtab.symbol_entries.len();
+    let num_locals = symtab.header.sh_info as usize;
+    let actual_locals = symtab
+        .symbol_entries
+        .iter()
+        .filter(|s| s.borrow().st_bind == STB_LOCAL)
+        .count();
+    if actual_locals != num_locals {
+        issues.push(format!(
+            "symtab: sh_info claims {} local symbols, but {} entries are STB_LOCAL",
+            num_locals, actual_locals
+        ));
+    }
+    for (i, s) in symtab.symbol_entries.iter().enumerate() {
+        let is_local = s.borrow().st_bind == STB_LOCAL;
+        if is_local != (i < num_locals) {
+            issues.push(format!(
+                "symtab: symbol {} (\"{}\") is {}STB_LOCAL but {} sh_info={}",
+                i,
+                String::from_utf8_lossy(&s.borrow().name),
+                if is_local { "" } else { "not " },
+                if is_local { "before" } else { "after" },
+                num_locals
+            ));
+        }
+    }
+
+    let mut seen_globals: HashMap<Vec<u8>, (usize, usize)> = HashMap::new();
+    for s in &symtab.symbol_entries {
+        let s = s.borrow();
+        if s.st_bind == STB_LOCAL || s.st_shndx == SHN_UNDEF || s.name.is_empty() {
+            continue;
+        }
+        match seen_globals.get(&s.name) {
+            Some(&(shndx, value)) if shndx != s.st_shndx || value != s.st_value => {
+                issues.push(format!(
+                    "symtab: global symbol \"{}\" is defined twice at different locations",
+                    String::from_utf8_lossy(&s.name)
+                ));
+            }
+            _ => {
+                seen_globals.insert(s.name.clone(), (s.st_shndx, s.st_value));
+            }
+        }
+    }
+
+    for sec in &objfile.sections {
+        if !sec.is_rel() {
+            continue;
+        }
+        if sec.header.sh_link as usize != objfile.symtab {
+            issues.push(format!(
+                "{}: sh_link {} does not point at the symbol table (section {})",
+                sec.name, sec.header.sh_link, objfile.symtab
+            ));
+        }
+        let Some(target) = objfile.sections.get(sec.header.sh_info as usize) else {
+            issues.push(format!(
+                "{}: sh_info {} does not name a real section",
+                sec.name, sec.header.sh_info
+            ));
+            continue;
+        };
+        for rel in &sec.relocations {
+            if rel.r_offset >= target.data.len() {
+                issues.push(format!(
+                    "{}: relocation r_offset {:#x} is out of bounds for section {} (size {:#x})",
+                    sec.name,
+                    rel.r_offset,
+                    target.name,
+                    target.data.len()
+                ));
+            }
+            if rel.sym_index >= num_syms {
+                issues.push(format!(
+                    "{}: relocation references symbol {}, but only {} symbols exist",
+                    sec.name, rel.sym_index, num_syms
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+/// Runs the GLOBAL_ASM/INCLUDE_ASM merge pipeline against an object file on
+/// disk. Thin wrapper around [`fixup_objfile_bytes`] for callers that have a
+/// real path; archive members are processed directly from their in-memory
+/// bytes instead, without being extracted to a file of their own.
 pub(crate) fn fixup_objfile(
     objfile_path: &PathBuf,
     functions: &[Function],
@@ -613,7 +1125,46 @@ pub(crate) fn fixup_objfile(
     output_enc: &Encoding,
     drop_mdebug_gptab: bool,
     convert_statics: SymbolVisibility,
+    target: Target,
+    validate: bool,
 ) -> Result<()> {
+    let objfile_data = fs::read(objfile_path)?;
+    let objfile_name = objfile_path.to_string_lossy().into_owned();
+    let new_data = fixup_objfile_bytes(
+        &objfile_data,
+        &objfile_name,
+        functions,
+        asm_prelude,
+        assembler,
+        output_enc,
+        drop_mdebug_gptab,
+        convert_statics,
+        target,
+        validate,
+    )?;
+    fs::write(objfile_path, new_data)?;
+    Ok(())
+}
+
+/// The bytes-based core of the merge pipeline: takes an object file's raw
+/// contents plus a display name (embedded in `GlobalWithFilename` static
+/// symbol names), and returns the rewritten object's bytes. `objfile_name`
+/// need not be a real path; archive members pass their member name.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fixup_objfile_bytes(
+    objfile_data: &[u8],
+    objfile_name: &str,
+    functions: &[Function],
+    asm_prelude: &str,
+    assembler: &str,
+    output_enc: &Encoding,
+    drop_mdebug_gptab: bool,
+    convert_statics: SymbolVisibility,
+    target: Target,
+    validate: bool,
+) -> Result<Vec<u8>> {
+    let mut validation_issues: Vec<String> = vec![];
+
     const OUTPUT_SECTIONS: [OutputSection; 4] = [
         OutputSection::Data,
         OutputSection::Text,
@@ -622,8 +1173,7 @@ pub(crate) fn fixup_objfile(
     ];
     const INPUT_SECTION_NAMES: [&str; 5] = [".data", ".text", ".rodata", ".bss", ".late_rodata"];
 
-    let objfile_data = fs::read(objfile_path)?;
-    let mut objfile = ElfFile::new(&objfile_data)?;
+    let mut objfile = ElfFile::new(objfile_data, target)?;
     let endian = objfile.endian;
 
     let mut prev_locs: EnumMap<OutputSection, usize> = EnumMap::default();
@@ -649,6 +1199,11 @@ pub(crate) fn fixup_objfile(
     // don't have to fix up relocations/symbol references.
     let mut all_text_glabels: HashSet<Vec<u8>> = HashSet::new();
     let mut func_sizes: HashMap<Vec<u8>, usize> = HashMap::new();
+    // glabels from an INCLUDE_ASM_STATIC/GLOBAL_ASM_STATIC block: their real
+    // symbol must come out STB_LOCAL, since `glabel` makes it global in the
+    // assembled object and the (static) dummy C function used to probe their
+    // size only controls its own placeholder symbol.
+    let mut local_text_glabels: HashSet<Vec<u8>> = HashSet::new();
 
     for function in functions.iter() {
         let text_glabels = function
@@ -683,7 +1238,7 @@ pub(crate) fn fixup_objfile(
                 asm.push(format!(".section {}", sectype));
                 if sectype == OutputSection::Text {
                     for _ in 0..((loc - prev_loc) / 4) {
-                        asm.push("nop".to_owned());
+                        asm.push(target.nop_line());
                     }
                 } else {
                     asm.push(format!(".space {}", loc - prev_loc));
@@ -703,6 +1258,9 @@ pub(crate) fn fixup_objfile(
 
         if !ifdefed {
             all_text_glabels.extend(text_glabels.iter().map(|x| x.to_vec()));
+            if function.is_static {
+                local_text_glabels.extend(text_glabels.iter().map(|x| x.to_vec()));
+            }
             all_late_rodata_dummy_bytes.push(function.late_rodata_dummy_bytes.clone());
             all_jtbl_rodata_size.push(function.jtbl_rodata_size);
             late_rodata_asm.extend(function.late_rodata_asm_conts.iter().cloned());
@@ -735,7 +1293,10 @@ pub(crate) fn fixup_objfile(
 
     let temp_dir = TempDir::with_prefix("asm_processor")?;
 
-    let obj_stem = objfile_path.file_stem().unwrap().to_str().unwrap();
+    let obj_stem = Path::new(objfile_name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("objfile");
 
     let o_file_path = temp_dir
         .path()
@@ -767,13 +1328,15 @@ pub(crate) fn fixup_objfile(
     if !status.success() {
         return Err(anyhow::anyhow!("Failed to assemble"));
     }
-    let asm_objfile = ElfFile::new(&fs::read(&o_file_path)?)?;
+    let asm_objfile = ElfFile::new(&fs::read(&o_file_path)?, target)?;
 
     // Remove clutter from objdump output for tests, and make the tests
     // portable by avoiding absolute paths. Outside of tests .mdebug is
-    // useful for showing source together with asm, though.
+    // useful for showing source together with asm, though. .mdebug/.gptab
+    // are MIPS-only ECOFF debug sections, so there's nothing to drop on
+    // other targets.
     let mdebug_section = objfile.find_section(".mdebug").cloned();
-    if drop_mdebug_gptab {
+    if drop_mdebug_gptab && target == Target::Mips {
         objfile.drop_mdebug_gptab();
     }
 
@@ -789,6 +1352,44 @@ pub(crate) fn fixup_objfile(
         }
     }
 
+    // Some assemblers emit extra sections alongside .text/.data that this
+    // function doesn't otherwise know about (.pdr, .gnu.attributes, a
+    // .comment section, ...). They aren't referenced by anything we merge
+    // above, so without this they'd just be dropped on the floor when
+    // asm_objfile goes out of scope; carry them over verbatim instead.
+    // Their own .rel/.rela sections (if any) are carried over further down,
+    // once relocation symbol indices have been fixed up.
+    let mut passthrough_source_indices = vec![];
+    for source in &asm_objfile.sections {
+        if source.index == 0
+            || source.is_rel()
+            || source.header.sh_type == SHT_SYMTAB
+            || source.header.sh_type == SHT_SYMTAB_SHNDX
+            || source.header.sh_type == SHT_STRTAB
+            || source.header.sh_type == SHT_MIPS_DEBUG
+            || source.header.sh_type == SHT_MIPS_GPTAB
+            || INPUT_SECTION_NAMES.contains(&source.name.as_str())
+            || source.name == ".reginfo"
+            || objfile.find_section(&source.name).is_some()
+        {
+            continue;
+        }
+        objfile.add_section(
+            &source.name,
+            &HeaderFields {
+                sh_type: source.header.sh_type,
+                sh_flags: source.header.sh_flags,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: source.header.sh_addralign,
+                sh_entsize: source.header.sh_entsize,
+            },
+            &source.data,
+            endian,
+        );
+        passthrough_source_indices.push(source.index);
+    }
+
     // Move over section contents
     let mut modified_text_positions = HashSet::new();
     let mut jtbl_rodata_positions: HashSet<usize> = HashSet::new();
@@ -879,6 +1480,7 @@ pub(crate) fn fixup_objfile(
         if source_end - source_pos != expected_size {
             return Err(anyhow::anyhow!("computed wrong size of .late_rodata"));
         }
+        let late_rodata_start = source_pos;
         let mut new_data = target.data.clone();
 
         for (dummy_bytes_list, &jtbl_rodata_size) in all_late_rodata_dummy_bytes
@@ -931,6 +1533,14 @@ pub(crate) fn fixup_objfile(
             }
         }
         target.data = new_data;
+
+        if validate && source_pos != source_end {
+            validation_issues.push(format!(
+                ".late_rodata: only consumed {:#x} of {:#x} bytes while moving it into .rodata",
+                source_pos - late_rodata_start,
+                expected_size
+            ));
+        }
     }
 
     // Merge strtab data.
@@ -965,6 +1575,9 @@ pub(crate) fn fixup_objfile(
         .cloned()
         .collect();
 
+    let mut late_rodata_has_base_symbol = false;
+    let mut late_rodata_hi_lo_fixed = false;
+
     for (i, s) in asm_objfile.symtab().symbol_entries.iter().enumerate() {
         let is_local = i < asm_objfile.symtab().header.sh_info as usize;
         if is_local && !relocated_symbols.contains(s) {
@@ -997,20 +1610,25 @@ pub(crate) fn fixup_objfile(
                     let size = func_sizes[&s.borrow().name];
                     s.borrow_mut().st_size = size;
                 }
+                // INCLUDE_ASM_STATIC/GLOBAL_ASM_STATIC: the dummy C function
+                // only ever controls the never-emitted placeholder symbol, so
+                // the real glabel symbol's binding must be rewritten here.
+                if local_text_glabels.contains(&s.borrow().name) {
+                    s.borrow_mut().st_bind = STB_LOCAL;
+                }
             }
             if section_name == ".late_rodata" {
-                if s.borrow().st_value == 0 {
+                let st_val = s.borrow().st_value;
+                if st_val == 0 && !moved_late_rodata.contains_key(&0) {
                     // This must be a symbol corresponding to the whole .late_rodata
-                    // section, being referred to from a relocation.
-                    // Moving local symbols is tricky, because it requires fixing up
-                    // lo16/hi16 relocation references to .late_rodata+<offset>.
-                    // Just disallow it for now.
-                    return Err(anyhow::anyhow!(
-                        "local symbols in .late_rodata are not allowed"
-                    ));
+                    // section, being referred to from a relocation: its offset is
+                    // carried by the relocation's hi16/lo16 addend rather than by
+                    // this symbol's value, and gets fixed up below once
+                    // .late_rodata's contents have been moved into .rodata.
+                    late_rodata_has_base_symbol = true;
+                } else {
+                    s.borrow_mut().st_value = moved_late_rodata[&st_val];
                 }
-                let st_val = s.borrow().st_value;
-                s.borrow_mut().st_value = moved_late_rodata[&st_val];
             }
         }
         s.borrow_mut().st_name += strtab_adj;
@@ -1031,108 +1649,131 @@ pub(crate) fn fixup_objfile(
         let mut strtab_index = objfile.sym_strtab().data.len();
         let mut new_strtab_data = vec![];
 
-        let read_u32 = |data: &[u8], offset| {
-            u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
-        };
+        for rec in read_mdebug_statics(&objfile_data, &mdebug_section.data, endian) {
+            let mut symbol_name = rec.name;
+            if rec.in_function {
+                // For in-function statics, append an increasing counter to
+                // the name, to avoid duplicate conflicting symbols.
+                let count = static_name_count.get(&symbol_name).unwrap_or(&0) + 1;
+                static_name_count.insert(symbol_name.clone(), count);
+                symbol_name.extend(format!(":{}", count).as_bytes());
+            }
+            let mut emitted_symbol_name = symbol_name.clone();
+            if convert_statics == SymbolVisibility::GlobalWithFilename {
+                // Change the emitted symbol name to include the filename,
+                // but don't let that affect deduplication logic (we still
+                // want to be able to reference statics from GLOBAL_ASM).
+                let mut new_name = objfile_name.as_bytes().to_vec();
+                new_name.push(b':');
+                new_name.extend(emitted_symbol_name);
+                emitted_symbol_name = new_name;
+            };
+            let section_name = match rec.sc {
+                1 => ".text",
+                2 => ".data",
+                3 => ".bss",
+                15 => ".rodata",
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "unsupported MIPS_DEBUG_SC value: {}",
+                        rec.sc
+                    ));
+                }
+            };
+            let section = objfile.find_section(section_name).unwrap();
+            let symtype = if rec.sc == 1 { STT_FUNC } else { STT_OBJECT };
+            let binding = if make_statics_global {
+                STB_GLOBAL
+            } else {
+                STB_LOCAL
+            };
+            let sym = Symbol {
+                st_name: strtab_index,
+                st_value: rec.value,
+                st_size: 0,
+                st_bind: binding,
+                st_type: symtype,
+                st_visibility: STV_DEFAULT,
+                st_shndx: section.index,
+                name: symbol_name,
+            };
+            strtab_index += emitted_symbol_name.len() + 1;
+            new_strtab_data.extend(&emitted_symbol_name);
+            new_strtab_data.push(b'\0');
+            new_syms.push(Rc::new(RefCell::new(sym)));
+        }
 
-        let ifd_max = read_u32(&mdebug_section.data, 18 * 4);
-        let cb_fd_offset = read_u32(&mdebug_section.data, 19 * 4);
-        let cb_sym_offset = read_u32(&mdebug_section.data, 9 * 4);
-        let cb_ss_offset = read_u32(&mdebug_section.data, 15 * 4);
-
-        for i in 0..ifd_max {
-            let offset = cb_fd_offset + 18 * 4 * i;
-            let iss_base = read_u32(&objfile.data, offset + 2 * 4);
-            let isym_base = read_u32(&objfile.data, offset + 4 * 4);
-            let csym = read_u32(&objfile.data, offset + 5 * 4);
-            let mut scope_level = 0;
-
-            for j in 0..csym {
-                let offset2 = cb_sym_offset + 12 * (isym_base + j);
-                let iss = read_u32(&objfile.data, offset2);
-                let value = read_u32(&objfile.data, offset2 + 4);
-                let st_sc_index = read_u32(&objfile.data, offset2 + 8);
-                let st = st_sc_index >> 26;
-                let sc = (st_sc_index >> 21) & 0x1F;
-
-                if st == MIPS_DEBUG_ST_STATIC || st == MIPS_DEBUG_ST_STATIC_PROC {
-                    let symbol_name_offset = cb_ss_offset + iss_base + iss;
-                    let symbol_name_offset_end = objfile_data
-                        .iter()
-                        .skip(symbol_name_offset)
-                        .position(|x| *x == 0)
-                        .unwrap()
-                        + symbol_name_offset;
-                    let mut symbol_name =
-                        objfile_data[symbol_name_offset..symbol_name_offset_end].to_owned();
-                    if scope_level > 1 {
-                        // For in-function statics, append an increasing counter to
-                        // the name, to avoid duplicate conflicting symbols.
-                        let count = static_name_count.get(&symbol_name).unwrap_or(&0) + 1;
-                        static_name_count.insert(symbol_name.clone(), count);
-                        symbol_name.extend(format!(":{}", count).as_bytes());
-                    }
-                    let mut emitted_symbol_name = symbol_name.clone();
-                    if convert_statics == SymbolVisibility::GlobalWithFilename {
-                        // Change the emitted symbol name to include the filename,
-                        // but don't let that affect deduplication logic (we still
-                        // want to be able to reference statics from GLOBAL_ASM).
-                        let mut new_name = objfile_path.to_string_lossy().into_owned().into_bytes();
-                        new_name.push(b':');
-                        new_name.extend(emitted_symbol_name);
-                        emitted_symbol_name = new_name;
-                    };
-                    let section_name = match sc {
-                        1 => ".text",
-                        2 => ".data",
-                        3 => ".bss",
-                        15 => ".rodata",
-                        _ => {
-                            return Err(anyhow::anyhow!("unsupported MIPS_DEBUG_SC value: {}", sc));
-                        }
-                    };
-                    let section = objfile.find_section(section_name).unwrap();
-                    let symtype = if sc == 1 { STT_FUNC } else { STT_OBJECT };
-                    let binding = if make_statics_global {
-                        STB_GLOBAL
-                    } else {
-                        STB_LOCAL
-                    };
-                    let sym = Symbol {
-                        st_name: strtab_index,
-                        st_value: value,
-                        st_size: 0,
-                        st_bind: binding,
-                        st_type: symtype,
-                        st_visibility: STV_DEFAULT,
-                        st_shndx: section.index,
-                        name: symbol_name,
-                    };
-                    strtab_index += emitted_symbol_name.len() + 1;
-                    new_strtab_data.extend(&emitted_symbol_name);
-                    new_strtab_data.push(b'\0');
-                    new_syms.push(Rc::new(RefCell::new(sym)));
+        objfile.sym_strtab_mut().data.extend(new_strtab_data);
+    } else if convert_statics != SymbolVisibility::No {
+        // No .mdebug (modern GCC, or LE-MIPS targets that never emit ECOFF
+        // debug info): fall back to recovering file-local statics from
+        // DWARF so GLOBAL_ASM can still refer to them.
+        let debug_info = objfile.find_section(".debug_info").cloned();
+        let debug_abbrev = objfile.find_section(".debug_abbrev").cloned();
+        if let (Some(debug_info), Some(debug_abbrev)) = (debug_info, debug_abbrev) {
+            let debug_str = objfile.find_section(".debug_str").map(|s| s.data.clone());
+            let dwarf_statics = crate::dwarf::find_static_symbols(
+                &debug_info.data,
+                &debug_abbrev.data,
+                debug_str.as_deref(),
+                endian,
+            );
+
+            let mut static_name_count: HashMap<Vec<u8>, usize> = HashMap::new();
+            let mut strtab_index = objfile.sym_strtab().data.len();
+            let mut new_strtab_data = vec![];
+
+            for sym in dwarf_statics {
+                let addr = sym.address as usize;
+                let Some(section) = [".text", ".data", ".rodata", ".bss"].iter().find_map(|name| {
+                    objfile.find_section(name).filter(|s| {
+                        addr >= s.header.sh_addr as usize
+                            && addr < s.header.sh_addr as usize + s.header.sh_size as usize
+                    })
+                }) else {
+                    continue;
+                };
+
+                let mut symbol_name = sym.name;
+                if sym.depth > 0 {
+                    // For in-function statics, append an increasing counter to
+                    // the name, to avoid duplicate conflicting symbols.
+                    let count = static_name_count.get(&symbol_name).unwrap_or(&0) + 1;
+                    static_name_count.insert(symbol_name.clone(), count);
+                    symbol_name.extend(format!(":{}", count).as_bytes());
                 }
-                match st {
-                    MIPS_DEBUG_ST_FILE
-                    | MIPS_DEBUG_ST_STRUCT
-                    | MIPS_DEBUG_ST_UNION
-                    | MIPS_DEBUG_ST_ENUM
-                    | MIPS_DEBUG_ST_BLOCK
-                    | MIPS_DEBUG_ST_PROC
-                    | MIPS_DEBUG_ST_STATIC_PROC => {
-                        scope_level += 1;
-                    }
-                    MIPS_DEBUG_ST_END => {
-                        scope_level -= 1;
-                    }
-                    _ => {}
+                let mut emitted_symbol_name = symbol_name.clone();
+                if convert_statics == SymbolVisibility::GlobalWithFilename {
+                    let mut new_name = objfile_name.as_bytes().to_vec();
+                    new_name.push(b':');
+                    new_name.extend(emitted_symbol_name);
+                    emitted_symbol_name = new_name;
                 }
+
+                let symtype = if sym.is_func { STT_FUNC } else { STT_OBJECT };
+                let binding = if make_statics_global {
+                    STB_GLOBAL
+                } else {
+                    STB_LOCAL
+                };
+                let new_sym = Symbol {
+                    st_name: strtab_index,
+                    st_value: addr - section.header.sh_addr as usize,
+                    st_size: 0,
+                    st_bind: binding,
+                    st_type: symtype,
+                    st_visibility: STV_DEFAULT,
+                    st_shndx: section.index,
+                    name: symbol_name,
+                };
+                strtab_index += emitted_symbol_name.len() + 1;
+                new_strtab_data.extend(&emitted_symbol_name);
+                new_strtab_data.push(b'\0');
+                new_syms.push(Rc::new(RefCell::new(new_sym)));
             }
-            assert_eq!(scope_level, 0);
-        }
 
-        objfile.sym_strtab_mut().data.extend(new_strtab_data);
+            objfile.sym_strtab_mut().data.extend(new_strtab_data);
+        }
     }
 
     // Get rid of duplicate symbols, favoring ones that are not UNDEF.
@@ -1208,7 +1849,10 @@ pub(crate) fn fixup_objfile(
         .iter()
         .filter(|x| x.borrow().st_bind == STB_LOCAL)
         .count();
-    let new_sym_data: Vec<u8> = new_syms.iter().flat_map(|s| s.borrow().to_bin()).collect();
+    let new_sym_data: Vec<u8> = new_syms
+        .iter()
+        .flat_map(|s| s.borrow().to_bin(endian))
+        .collect();
     let mut new_index: HashMap<Vec<u8>, usize> = HashMap::new();
 
     for (i, s) in new_syms.iter().enumerate() {
@@ -1254,7 +1898,44 @@ pub(crate) fn fixup_objfile(
         }
     }
 
+    // Carry over relocations against the passthrough sections copied above,
+    // remapping symbol indices the same way the main relocation fixup does,
+    // so e.g. a custom note section with its own .rel/.rela isn't silently
+    // dropped while its data is "preserved verbatim".
+    for source_index in &passthrough_source_indices {
+        let source = &asm_objfile.sections[*source_index];
+        let target_index = objfile.find_section(&source.name).unwrap().index;
+        for reltab_idx in &source.relocated_by {
+            let reltab = &asm_objfile.sections[*reltab_idx];
+            let symbol_entries = &asm_objfile.symtab().symbol_entries;
+            let nrels: Vec<Relocation> = reltab
+                .relocations
+                .iter()
+                .map(|rel| {
+                    let mut rel = rel.clone();
+                    rel.sym_index = new_index[&symbol_entries[rel.sym_index].borrow().name];
+                    rel
+                })
+                .collect();
+            let new_data: Vec<u8> = nrels.iter().flat_map(|x| x.to_bin(endian)).collect();
+            objfile.add_section(
+                &reltab.name,
+                &HeaderFields {
+                    sh_type: reltab.header.sh_type,
+                    sh_flags: reltab.header.sh_flags,
+                    sh_link: objfile.symtab().index as u32,
+                    sh_info: target_index as u32,
+                    sh_addralign: reltab.header.sh_addralign,
+                    sh_entsize: reltab.header.sh_entsize,
+                },
+                &new_data,
+                endian,
+            );
+        }
+    }
+
     // Move over relocations
+    let rodata_index = objfile.find_section(".rodata").map(|s| s.index);
     for sectype in INPUT_SECTION_NAMES.iter() {
         if let Some(source) = asm_objfile.find_section(sectype) {
             if source.data.is_empty() {
@@ -1269,13 +1950,63 @@ pub(crate) fn fixup_objfile(
             let target_index = objfile.find_section(target_sectype).unwrap().index;
             for reltab in &source.relocated_by {
                 let reltab = &mut asm_objfile.sections[*reltab].clone();
+
+                // hi16 carries no usable value of its own; it's combined with
+                // the *next* lo16 relocation against the same symbol to form
+                // the full 32-bit value, so stash pending hi16 offsets here
+                // (keyed by final symbol index) until that lo16 shows up.
+                let mut pending_hi16: HashMap<usize, Vec<usize>> = HashMap::new();
+                let reltab_sh_type = reltab.header.sh_type;
+
                 for rel in &mut reltab.relocations {
-                    rel.sym_index = new_index[&asm_objfile.symtab().symbol_entries[rel.sym_index]
-                        .borrow()
-                        .name];
+                    let orig_sym = asm_objfile.symtab().symbol_entries[rel.sym_index].clone();
+                    rel.sym_index = new_index[&orig_sym.borrow().name];
                     if *sectype == ".late_rodata" {
                         rel.r_offset = moved_late_rodata[&rel.r_offset];
                     }
+
+                    let targets_late_rodata_base = Some(orig_sym.borrow().st_shndx)
+                        == rodata_index
+                        && orig_sym.borrow().st_value == 0;
+                    if !targets_late_rodata_base
+                        || (rel.rel_type != R_MIPS_HI16 && rel.rel_type != R_MIPS_LO16)
+                    {
+                        continue;
+                    }
+
+                    if reltab_sh_type == SHT_RELA {
+                        // RELA carries the full addend directly; there are no
+                        // instruction immediates to decode.
+                        let old_value = rel.r_addend.unwrap_or(0) as usize;
+                        let new_value = *moved_late_rodata.get(&old_value).ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "could not fix up relocation into .late_rodata+{:#x}",
+                                old_value
+                            )
+                        })?;
+                        rel.r_addend = Some(new_value as u32);
+                        late_rodata_hi_lo_fixed = true;
+                    } else if rel.rel_type == R_MIPS_HI16 {
+                        pending_hi16
+                            .entry(rel.sym_index)
+                            .or_default()
+                            .push(rel.r_offset);
+                    } else if let Some(hi_offsets) = pending_hi16.remove(&rel.sym_index) {
+                        // binutils allows multiple HI16s against the same
+                        // symbol to precede one LO16, each reusing its low
+                        // bits for sign-extension; fix up every one of them,
+                        // not just the most recently pushed.
+                        for hi_offset in hi_offsets {
+                            fixup_late_rodata_hi16_lo16(
+                                objfile.find_section_mut(target_sectype).unwrap(),
+                                hi_offset,
+                                rel.r_offset,
+                                &moved_late_rodata,
+                                endian,
+                            )?;
+                        }
+                        late_rodata_hi_lo_fixed = true;
+                    }
                 }
                 let new_data: Vec<u8> = reltab
                     .relocations
@@ -1311,11 +2042,170 @@ pub(crate) fn fixup_objfile(
         }
     }
 
-    let mut file = std::fs::File::create(objfile_path).unwrap();
-    let mut writer = BufWriter::new(&mut file);
+    if late_rodata_has_base_symbol && !late_rodata_hi_lo_fixed {
+        return Err(anyhow::anyhow!(
+            "local symbols in .late_rodata are not allowed, except when referred to \
+             exclusively via hi16/lo16 relocations"
+        ));
+    }
+
+    // The symtab's data was rebuilt from new_syms above; make its entries
+    // match, so validation and the SHT_SYMTAB_SHNDX regeneration in write()
+    // see the merged symbols rather than the original ones.
+    objfile.symtab_mut().symbol_entries = new_syms;
+
+    if validate {
+        validation_issues.extend(validate_objfile(&objfile));
+        if !validation_issues.is_empty() {
+            return Err(anyhow::anyhow!(
+                "--validate found {} issue(s) with the merged object:\n{}",
+                validation_issues.len(),
+                validation_issues
+                    .iter()
+                    .map(|issue| format!("  - {}", issue))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ));
+        }
+    }
+
+    let mut writer = Cursor::new(Vec::new());
     objfile.write(&mut writer)?;
 
     fs::remove_file(s_file_path)?;
     fs::remove_file(o_file_path)?;
-    Ok(())
+    Ok(writer.into_inner())
+}
+
+/// The names of every globally-visible, defined symbol in an ELF object.
+/// Used to regenerate an archive's symbol index after a member is rewritten
+/// in place.
+pub(crate) fn global_symbol_names(data: &[u8], target: Target) -> Result<Vec<Vec<u8>>> {
+    let elf = ElfFile::new(data, target)?;
+    Ok(elf
+        .symtab()
+        .symbol_entries
+        .iter()
+        .filter(|s| {
+            let s = s.borrow();
+            s.st_bind != STB_LOCAL && s.st_shndx != SHN_UNDEF && !s.name.is_empty()
+        })
+        .map(|s| s.borrow().name.clone())
+        .collect())
+}
+
+/// The `sh_size` of every named section in an object file, keyed by section
+/// name. Used by the two-pass macro-size probe to measure how large a
+/// section the assembler actually produced, when its size can't be computed
+/// by statically parsing the source.
+pub(crate) fn section_sizes(data: &[u8], target: Target) -> Result<HashMap<String, usize>> {
+    let elf = ElfFile::new(data, target)?;
+    Ok(elf
+        .sections
+        .iter()
+        .map(|s| (s.name.clone(), s.header.sh_size as usize))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a minimal .mdebug symbolic header plus the file descriptor,
+    // symbol record and string table it points at, all in the given byte
+    // order, describing a single MIPS_DEBUG_ST_STATIC symbol named "foo"
+    // at value 0x1000 in storage class 2 (.data).
+    fn build_mdebug_fixture(endian: Endian) -> (Vec<u8>, Vec<u8>) {
+        let put_u32 = |data: &mut Vec<u8>, v: u32| match endian {
+            Endian::Big => data.extend(v.to_be_bytes()),
+            Endian::Little => data.extend(v.to_le_bytes()),
+        };
+
+        const CB_FD_OFFSET: u32 = 0;
+        const CB_SYM_OFFSET: u32 = 72; // right after the one field descriptor
+        const CB_SS_OFFSET: u32 = 84; // right after the one symbol record
+
+        let mut file_data = vec![];
+        // Field descriptor (18 words): only iss_base (word 2), isym_base
+        // (word 4) and csym (word 5) matter here.
+        for word in 0..18u32 {
+            match word {
+                2 => put_u32(&mut file_data, 0), // iss_base
+                4 => put_u32(&mut file_data, 0), // isym_base
+                5 => put_u32(&mut file_data, 1), // csym
+                _ => put_u32(&mut file_data, 0),
+            }
+        }
+        assert_eq!(file_data.len(), CB_SYM_OFFSET as usize);
+
+        // One symbol record: iss, value, then (st << 26) | (sc << 21).
+        let st = MIPS_DEBUG_ST_STATIC as u32;
+        let sc = 2u32; // .data
+        put_u32(&mut file_data, 0); // iss
+        put_u32(&mut file_data, 0x1000); // value
+        put_u32(&mut file_data, (st << 26) | (sc << 21));
+        assert_eq!(file_data.len(), CB_SS_OFFSET as usize);
+
+        file_data.extend(b"foo\0");
+
+        let mut mdebug_data = vec![0u8; 20 * 4];
+        let patch_word = |data: &mut [u8], word: usize, v: u32| {
+            let bytes = match endian {
+                Endian::Big => v.to_be_bytes(),
+                Endian::Little => v.to_le_bytes(),
+            };
+            data[word * 4..word * 4 + 4].copy_from_slice(&bytes);
+        };
+        patch_word(&mut mdebug_data, 9, CB_SYM_OFFSET);
+        patch_word(&mut mdebug_data, 15, CB_SS_OFFSET);
+        patch_word(&mut mdebug_data, 18, 1); // ifd_max
+        patch_word(&mut mdebug_data, 19, CB_FD_OFFSET);
+
+        (file_data, mdebug_data)
+    }
+
+    #[test]
+    fn mdebug_statics_decode_big_endian() {
+        let (file_data, mdebug_data) = build_mdebug_fixture(Endian::Big);
+        let statics = read_mdebug_statics(&file_data, &mdebug_data, Endian::Big);
+        assert_eq!(statics.len(), 1);
+        assert_eq!(statics[0].name, b"foo");
+        assert_eq!(statics[0].value, 0x1000);
+        assert_eq!(statics[0].sc, 2);
+        assert!(!statics[0].in_function);
+    }
+
+    #[test]
+    fn mdebug_statics_decode_little_endian() {
+        let (file_data, mdebug_data) = build_mdebug_fixture(Endian::Little);
+        let statics = read_mdebug_statics(&file_data, &mdebug_data, Endian::Little);
+        assert_eq!(statics.len(), 1);
+        assert_eq!(statics[0].name, b"foo");
+        assert_eq!(statics[0].value, 0x1000);
+        assert_eq!(statics[0].sc, 2);
+        assert!(!statics[0].in_function);
+    }
+
+    #[test]
+    fn symbol_to_bin_respects_endian() {
+        let sym = Symbol {
+            st_name: 1,
+            st_value: 0x1000,
+            st_size: 0x10,
+            st_shndx: 2,
+            st_type: STT_OBJECT,
+            st_bind: STB_GLOBAL,
+            st_visibility: 0,
+            name: b"foo".to_vec(),
+        };
+
+        let big = sym.to_bin(Endian::Big);
+        let little = sym.to_bin(Endian::Little);
+        assert_ne!(big, little);
+
+        assert_eq!(&big[0..4], &0x1u32.to_be_bytes());
+        assert_eq!(&big[4..8], &0x1000u32.to_be_bytes());
+        assert_eq!(&little[0..4], &0x1u32.to_le_bytes());
+        assert_eq!(&little[4..8], &0x1000u32.to_le_bytes());
+    }
 }
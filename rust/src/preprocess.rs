@@ -1,8 +1,18 @@
-use std::{collections::HashMap, fs::read_to_string, io::Write, iter, path::Path, sync::OnceLock};
+use std::{
+    collections::HashMap,
+    fs::{self, read_to_string, File},
+    io::Write,
+    iter,
+    path::Path,
+    process::Command,
+    sync::OnceLock,
+};
 
 use anyhow::Result;
 use regex::Regex;
+use temp_dir::TempDir;
 
+use crate::postprocess::{section_sizes, Target};
 use crate::{AsmProcArgs, Encoding, Function, GlobalState, OptLevel, RunResult};
 
 use anyhow::Context;
@@ -14,6 +24,16 @@ enum Section {
     Rodata,
     LateRodata,
     Bss,
+    /// Small, initialized, gp-relative data (MIPS `-G` small-data threshold).
+    Sdata,
+    /// Small, initialized, gp-relative read-only data, kept apart from
+    /// `.sdata` by toolchains (e.g. IDO) that place const small data here.
+    Sdata2,
+    /// Small, uninitialized, gp-relative data.
+    Sbss,
+    /// Initialized data containing relocations the dynamic linker fixes up
+    /// and then the loader can make read-only (ELF `DT_FLAGS/RELRO`).
+    DataRelRo,
 }
 
 impl Section {
@@ -24,47 +44,316 @@ impl Section {
             ".rodata" => Some(Section::Rodata),
             ".late_rodata" => Some(Section::LateRodata),
             ".bss" => Some(Section::Bss),
+            ".sdata" => Some(Section::Sdata),
+            ".sdata2" => Some(Section::Sdata2),
+            ".sbss" => Some(Section::Sbss),
+            ".data.rel.ro" => Some(Section::DataRelRo),
             _ => None,
         }
     }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Section::Text => ".text",
+            Section::Data => ".data",
+            Section::Rodata => ".rodata",
+            Section::LateRodata => ".late_rodata",
+            Section::Bss => ".bss",
+            Section::Sdata => ".sdata",
+            Section::Sdata2 => ".sdata2",
+            Section::Sbss => ".sbss",
+            Section::DataRelRo => ".data.rel.ro",
+        }
+    }
+}
+
+/// Which target architecture's compiler the input was generated for,
+/// selected with `--arch` and used to look up an [`Arch`] impl.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ArchKind {
+    Mips,
+    Ppc,
+}
+
+/// Per-target tuning for the dummy-C-generation pipeline. `parse_source`
+/// used to bake in IDO/MIPS-specific numbers directly; an `Arch` impl
+/// supplies them instead, so new backends (PPC, RISC-V, ...) plug in here
+/// rather than extending a match arm that only makes sense for one target.
+pub(crate) trait Arch {
+    /// `(min_instr_count, skip_instr_count, prelude_if_late_rodata)` for the
+    /// given compiler flags: how many instructions of prologue/epilogue
+    /// padding must be left alone before dummy stores can be interleaved,
+    /// and how long a PIC prelude is when late rodata follows it.
+    fn instr_counts(&self, args: &AsmProcArgs) -> Result<(usize, usize, usize)>;
+
+    /// Whether this target's compiler emits a jump table for a `switch` over
+    /// a dense case range at these optimization settings, which lets
+    /// `finish` pack late rodata more densely than one dummy store per word.
+    fn use_jtbl_for_rodata(&self, args: &AsmProcArgs) -> bool;
+
+    /// Renders the dummy C declaration that reserves `size` bytes of a
+    /// non-canonical named section (discovered via `.section`, as opposed to
+    /// one of `.text`/`.data`/`.rodata`/`.bss`) under `new_name`, in case a
+    /// target needs different alignment padding or a different
+    /// section-placement attribute than plain GNU C `__attribute__((section(...)))`.
+    fn reserve_section(&self, name: &str, kind: Section, size: usize, new_name: &str) -> String {
+        match kind {
+            Section::Rodata => format!(
+                "const char {} __attribute__((section(\"{}\"))) [{}] = {{1}};",
+                new_name, name, size
+            ),
+            Section::Bss | Section::Sbss => format!(
+                "char {} __attribute__((section(\"{}\"))) [{}];",
+                new_name, name, size
+            ),
+            _ => format!(
+                "char {} __attribute__((section(\"{}\"))) [{}] = {{1}};",
+                new_name, name, size
+            ),
+        }
+    }
+
+    /// Byte order of this target, used to decide which 32-bit half of a
+    /// double-precision constant rewritten by `repl_float_hex` comes first.
+    fn is_big_endian(&self) -> bool {
+        true
+    }
+
+    /// The `postprocess::Target` this backend's assembler produces objects
+    /// for, so two-pass probing reads them back with the right `e_machine`
+    /// instead of assuming MIPS.
+    fn target(&self) -> Target;
+}
+
+/// The historical IDO/MIPS tuning: the `(min_instr_count, skip_instr_count)`
+/// table keyed on `OptLevel`/`framepointer`/`g3`, the 3-instruction `kpic`
+/// PIC prelude, and the `-O2`-or-`-g3`-without-framepointer jump-table
+/// heuristic.
+pub(crate) struct Mips;
+
+impl Arch for Mips {
+    fn instr_counts(&self, args: &AsmProcArgs) -> Result<(usize, usize, usize)> {
+        let (mut min_instr_count, mut skip_instr_count) = match (args.opt.clone(), args.g3) {
+            (OptLevel::O0, false) => match args.framepointer {
+                true => (8, 8),
+                false => (4, 4),
+            },
+            (OptLevel::O1, false) | (OptLevel::O2, false) => match args.framepointer {
+                true => (6, 5),
+                false => (2, 1),
+            },
+            (OptLevel::G, false) => match args.framepointer {
+                true => (7, 7),
+                false => (4, 4),
+            },
+            (OptLevel::O2, true) => match args.framepointer {
+                true => (4, 4),
+                false => (2, 2),
+            },
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unsupported optimization level: -g3 can only be used with -O2"
+                ))
+                .context("Invalid arguments")
+            }
+        };
+
+        let mut prelude_if_late_rodata = 0;
+        if args.kpic {
+            // Without optimizations, the PIC prelude always takes up 3 instructions.
+            // With optimizations, the prelude is optimized out if there's no late rodata.
+            if args.opt == OptLevel::O2 || args.g3 {
+                prelude_if_late_rodata = 3;
+            } else {
+                min_instr_count += 3;
+                skip_instr_count += 3;
+            }
+        }
+
+        Ok((min_instr_count, skip_instr_count, prelude_if_late_rodata))
+    }
+
+    fn use_jtbl_for_rodata(&self, args: &AsmProcArgs) -> bool {
+        (args.opt == OptLevel::O2 || args.g3) && !args.framepointer && !args.kpic
+    }
+
+    fn target(&self) -> Target {
+        Target::Mips
+    }
 }
 
+/// A starting-point PPC backend (e.g. for Metrowerks-compiled GC/Wii decomp
+/// projects): no PIC prelude (PPC codegen here doesn't use one), and no
+/// jump-table packing of late rodata until that codegen pattern has actually
+/// been characterized for a PPC compiler. The instruction counts mirror
+/// MIPS's non-`-g3` table as a reasonable starting calibration; projects
+/// using this backend will likely need to adjust them for their compiler.
+pub(crate) struct Ppc;
+
+impl Arch for Ppc {
+    fn instr_counts(&self, args: &AsmProcArgs) -> Result<(usize, usize, usize)> {
+        if args.g3 {
+            return Err(anyhow::anyhow!("-g3 is not supported by the ppc backend"))
+                .context("Invalid arguments");
+        }
+        let (min_instr_count, skip_instr_count) = match args.opt.clone() {
+            OptLevel::O0 => match args.framepointer {
+                true => (8, 8),
+                false => (4, 4),
+            },
+            OptLevel::O1 | OptLevel::O2 => match args.framepointer {
+                true => (6, 5),
+                false => (2, 1),
+            },
+            OptLevel::G => match args.framepointer {
+                true => (7, 7),
+                false => (4, 4),
+            },
+        };
+        Ok((min_instr_count, skip_instr_count, 0))
+    }
+
+    fn use_jtbl_for_rodata(&self, _args: &AsmProcArgs) -> bool {
+        false
+    }
+
+    fn target(&self) -> Target {
+        Target::Ppc
+    }
+}
+
+fn select_arch(kind: ArchKind) -> Box<dyn Arch> {
+    match kind {
+        ArchKind::Mips => Box::new(Mips),
+        ArchKind::Ppc => Box::new(Ppc),
+    }
+}
+
+/// Mnemonics known to always assemble to exactly one 4-byte MIPS instruction,
+/// including the handful of pseudo-ops (`move`, `nop`, `b`, ...) that GNU as
+/// expands 1:1. Anything else might be a macro or a pseudo-op whose expansion
+/// depends on its operands (`la`, `li`, `dla`, ...), so its size can't be
+/// predicted without actually assembling it.
+const FIXED_SIZE_MNEMONICS: &[&str] = &[
+    "add", "addu", "addi", "addiu", "sub", "subu", "and", "andi", "or", "ori", "xor", "xori",
+    "nor", "sll", "srl", "sra", "sllv", "srlv", "srav", "slt", "slti", "sltu", "sltiu", "lui",
+    "lw", "lh", "lhu", "lb", "lbu", "lwl", "lwr", "sw", "sh", "sb", "swl", "swr", "jal", "jr", "j",
+    "jalr", "beq", "bne", "blez", "bgtz", "bltz", "bgez", "bltzal", "bgezal", "mfc0", "mtc0",
+    "mfc1", "mtc1", "mfhi", "mflo", "mthi", "mtlo", "mult", "multu", "div", "divu", "break",
+    "syscall", "nop", "move", "b", "lwc1", "swc1", "ldc1", "sdc1", "add.s", "add.d", "sub.s",
+    "sub.d", "mul.s", "mul.d", "div.s", "div.d", "mov.s", "mov.d", "cvt.s.w", "cvt.d.w",
+    "cvt.w.s", "cvt.w.d", "cvt.s.d", "cvt.d.s", "c.eq.s", "c.eq.d", "c.lt.s", "c.lt.d", "c.le.s",
+    "c.le.d", "trunc.w.s", "trunc.w.d",
+];
+
+/// The canonical section names every `GlobalAsmBlock` starts out tracking,
+/// in emission order. Anything else (`.rodata.str1.4`, `.data.rel.ro`,
+/// `-ffunction-sections`/`-fdata-sections` output, ...) is discovered on the
+/// fly from `.section` directives and appended to `section_names`.
+const CANONICAL_SECTION_NAMES: [&str; 5] =
+    [".text", ".data", ".rodata", ".bss", ".late_rodata"];
+
 #[derive(Clone, Debug)]
 pub struct GlobalAsmBlock {
     fn_desc: String,
     cur_section: Section,
+    /// The literal name of the currently active section (e.g. `.rodata` or
+    /// `.rodata.str1.4`); `cur_section` only carries its *kind*.
+    cur_section_name: String,
     asm_conts: Vec<String>,
     late_rodata_asm_conts: Vec<String>,
     late_rodata_alignment: usize,
     late_rodata_alignment_from_context: bool,
     text_glabels: Vec<String>,
-    fn_section_sizes: HashMap<Section, usize>,
+    /// Running byte size of every section seen so far, keyed by its literal
+    /// name rather than just its kind, since e.g. `.rodata` and
+    /// `.rodata.str1.4` lay out independently of each other.
+    fn_section_sizes: HashMap<String, usize>,
+    /// Distinct section names seen, in first-seen order (the five canonical
+    /// ones are seeded up front so their relative emission order matches
+    /// historical behavior).
+    section_names: Vec<String>,
+    /// Kind of each section name in `section_names`, so `finish` knows how
+    /// to size/emit a dummy global for it.
+    section_kinds: HashMap<String, Section>,
     fn_ins_inds: Vec<(usize, usize)>,
     glued_line: String,
     num_lines: usize,
+    two_pass_macros: bool,
+    /// Lines whose size couldn't be determined by statically parsing the
+    /// source (an unrecognized .text mnemonic, or any instruction/macro in a
+    /// non-.text section), recorded so `finish` can measure their real size
+    /// by assembling the block. Only populated when `two_pass_macros` is set;
+    /// otherwise a non-.text instruction is a hard error and a .text line is
+    /// counted as one 4-byte instruction, same as before this existed.
+    unresolved_lines: Vec<(Section, usize)>,
+    /// Set from `INCLUDE_ASM_STATIC`/`GLOBAL_ASM_STATIC`: the synthesized
+    /// dummy function `finish` emits is declared `static`, for helpers whose
+    /// names would otherwise collide across translation units.
+    is_static: bool,
 }
 
 impl GlobalAsmBlock {
-    pub fn new(fn_desc: String) -> Self {
+    pub fn new(fn_desc: String, two_pass_macros: bool, is_static: bool) -> Self {
+        let section_names: Vec<String> = CANONICAL_SECTION_NAMES
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let section_kinds = HashMap::from([
+            (".text".to_string(), Section::Text),
+            (".data".to_string(), Section::Data),
+            (".bss".to_string(), Section::Bss),
+            (".rodata".to_string(), Section::Rodata),
+            (".late_rodata".to_string(), Section::LateRodata),
+        ]);
         Self {
             fn_desc,
             cur_section: Section::Text,
+            cur_section_name: ".text".to_string(),
             asm_conts: vec![],
             late_rodata_asm_conts: vec![],
             late_rodata_alignment: 0,
             late_rodata_alignment_from_context: false,
             text_glabels: vec![],
-            fn_section_sizes: HashMap::from([
-                (Section::Text, 0),
-                (Section::Data, 0),
-                (Section::Bss, 0),
-                (Section::Rodata, 0),
-                (Section::LateRodata, 0),
-            ]),
+            fn_section_sizes: section_names.iter().map(|s| (s.clone(), 0)).collect(),
+            section_names,
+            section_kinds,
             fn_ins_inds: vec![],
             glued_line: String::new(),
             num_lines: 0,
+            two_pass_macros,
+            unresolved_lines: vec![],
+            is_static,
+        }
+    }
+
+    /// Switches the active section to `name`, recognizing the small-data
+    /// variants (`.sdata`/`.sdata2`/`.sbss`/`.data.rel.ro`) and arbitrary
+    /// `.rodata*`/`.data*`/`.bss*` names (e.g. from
+    /// `-ffunction-sections`/`-fdata-sections` output) in addition to the
+    /// five canonical section names, so such input doesn't have to be
+    /// hand-edited before it can be processed.
+    fn set_section(&mut self, name: &str) -> Result<()> {
+        let name = if name == ".rdata" {
+            ".rodata".to_string()
+        } else {
+            name.to_string()
+        };
+        let kind = match Section::from_str(&name) {
+            Some(s) => s,
+            None if name.starts_with(".rodata") => Section::Rodata,
+            None if name.starts_with(".data") => Section::Data,
+            None if name.starts_with(".bss") => Section::Bss,
+            None => return Err(anyhow::anyhow!("Unknown section: {}", name)),
+        };
+        if !self.section_kinds.contains_key(&name) {
+            self.section_kinds.insert(name.clone(), kind);
+            self.section_names.push(name.clone());
+            self.fn_section_sizes.insert(name.clone(), 0);
         }
+        self.cur_section = kind;
+        self.cur_section_name = name;
+        Ok(())
     }
 
     fn re_comment_replacer(caps: &regex::Captures) -> String {
@@ -155,12 +444,40 @@ impl GlobalAsmBlock {
     }
 
     fn align(&mut self, n: usize) {
-        let size = self.fn_section_sizes.get_mut(&self.cur_section).unwrap();
+        let size = self
+            .fn_section_sizes
+            .get_mut(&self.cur_section_name)
+            .unwrap();
         while *size % n != 0 {
             *size += 1;
         }
     }
 
+    /// Checks that an explicit `.align`/`.balign` request within
+    /// `.late_rodata` is consistent with the alignment already implied by an
+    /// earlier `.double` or `.late_rodata_alignment` directive, rather than
+    /// silently accepting a larger alignment the generated dummy C code has
+    /// no way to honor (it can only emit floats or doubles, i.e. 4 or 8).
+    fn check_late_rodata_alignment(&mut self, n: usize, real_line: &str) -> Result<()> {
+        if self.cur_section != Section::LateRodata || n <= 4 {
+            return Ok(());
+        }
+        if n > 8 {
+            return Err(anyhow::anyhow!(format!(
+                ".late_rodata alignment requests greater than 8 bytes are not supported\n{}",
+                real_line
+            )));
+        }
+        if self.late_rodata_alignment != 0 && self.late_rodata_alignment != n {
+            return Err(anyhow::anyhow!(format!(
+                ".late_rodata_alignment alignment assumption conflicts with earlier .double directive. Make sure to provide explicit alignment padding."
+            )));
+        }
+        self.late_rodata_alignment = n;
+        self.late_rodata_alignment_from_context = false;
+        Ok(())
+    }
+
     fn add_sized(&mut self, size: isize, line: &str) -> Result<()> {
         if (self.cur_section == Section::Text || self.cur_section == Section::LateRodata)
             && size % 4 != 0
@@ -172,7 +489,10 @@ impl GlobalAsmBlock {
             return Err(anyhow::anyhow!("size cannot be negative {}", line));
         }
 
-        *self.fn_section_sizes.get_mut(&self.cur_section).unwrap() += size as usize;
+        *self
+            .fn_section_sizes
+            .get_mut(&self.cur_section_name)
+            .unwrap() += size as usize;
 
         if self.cur_section == Section::Text {
             if self.text_glabels.is_empty() {
@@ -231,20 +551,25 @@ impl GlobalAsmBlock {
         } else if line.starts_with(".section")
             || matches!(
                 line.as_str(),
-                ".text" | ".data" | ".rdata" | ".rodata" | ".bss" | ".late_rodata"
+                ".text"
+                    | ".data"
+                    | ".rdata"
+                    | ".rodata"
+                    | ".bss"
+                    | ".late_rodata"
+                    | ".sdata"
+                    | ".sdata2"
+                    | ".sbss"
             )
         {
             // section change
-            self.cur_section = if line == ".rdata" {
-                Section::Rodata
+            let name = if line == ".rdata" {
+                ".rdata".to_string()
             } else {
                 let first_arg = line.split(',').next().unwrap().to_string();
-                let name = first_arg.split_whitespace().last().unwrap();
-                match Section::from_str(name) {
-                    Some(s) => s,
-                    None => return Err(anyhow::anyhow!("Unknown section: {}", name)),
-                }
+                first_arg.split_whitespace().last().unwrap().to_string()
             };
+            self.set_section(&name)?;
 
             changed_section = true;
         } else if line.starts_with(".late_rodata_alignment") {
@@ -275,15 +600,23 @@ impl GlobalAsmBlock {
         } else if line.starts_with(".word")
             || line.starts_with(".gpword")
             || line.starts_with(".float")
+            || line.starts_with(".4byte")
         {
             self.align(4);
 
             self.add_sized(4 * line.split(',').count() as isize, &real_line)?;
+        } else if line.starts_with(".quad")
+            || line.starts_with(".dword")
+            || line.starts_with(".8byte")
+        {
+            self.align(8);
+
+            self.add_sized(8 * line.split(',').count() as isize, &real_line)?;
         } else if line.starts_with(".double") {
             self.align(4);
 
             if self.cur_section == Section::LateRodata {
-                let align8 = self.fn_section_sizes[&self.cur_section] % 8;
+                let align8 = self.fn_section_sizes[self.cur_section_name.as_str()] % 8;
                 // Automatically set late_rodata_alignment, so the generated C code uses doubles.
                 // This gives us correct alignment for the transferred doubles even when the
                 // late_rodata_alignment is wrong, e.g. for non-matching compilation.
@@ -305,27 +638,26 @@ impl GlobalAsmBlock {
                 self.add_sized(8 * line.split(',').count() as isize, &real_line)?;
                 emitting_double = true;
             }
-        } else if line.starts_with(".space") {
+        } else if line.starts_with(".space") || line.starts_with(".zero") {
             let size = line.split_whitespace().nth(1).unwrap().parse::<isize>()?;
             self.add_sized(size, &real_line)?;
         } else if line.starts_with(".balign") {
             let align = line.split_whitespace().nth(1).unwrap().parse::<isize>()?;
-            if align != 4 {
+            if align <= 0 || (align as usize).count_ones() != 1 {
                 return Err(anyhow::anyhow!(format!(
-                    "only .balign 4 is supported, found .balign {}",
+                    ".balign argument must be a power of two, found .balign {}",
                     align
                 )));
             }
-            self.align(4);
+            self.check_late_rodata_alignment(align as usize, &real_line)?;
+            self.align(align as usize);
         } else if line.starts_with(".align") {
-            let align = line.split_whitespace().nth(1).unwrap().parse::<isize>()?;
-            if align != 2 {
-                return Err(anyhow::anyhow!(format!(
-                    "only .align 2 is supported, found .align {}",
-                    align
-                )));
-            }
-            self.align(4);
+            // GNU as semantics: the argument is a power-of-two exponent, so
+            // `.align 3` means align to 8 bytes, not 3.
+            let exponent = line.split_whitespace().nth(1).unwrap().parse::<u32>()?;
+            let align = 1usize << exponent;
+            self.check_late_rodata_alignment(align, &real_line)?;
+            self.align(align);
         } else if line.starts_with(".asci") {
             let z = line.starts_with(".asciz") || line.starts_with(".asciiz");
             self.add_sized(
@@ -337,6 +669,7 @@ impl GlobalAsmBlock {
         } else if line.starts_with(".half")
             || line.starts_with(".hword")
             || line.starts_with(".short")
+            || line.starts_with(".2byte")
         {
             self.align(2);
             self.add_sized(2 * line.split(',').count() as isize, &real_line)?;
@@ -347,21 +680,33 @@ impl GlobalAsmBlock {
                 real_line
             )));
         } else {
-            // Unfortunately, macros are hard to support for .rodata --
-            // we don't know how how space they will expand to before
-            // running the assembler, but we need that information to
-            // construct the C code. So if we need that we'll either
-            // need to run the assembler twice (at least in some rare
-            // cases), or change how this program is invoked.
-            // Similarly, we can't currently deal with pseudo-instructions
-            // that expand to several real instructions.
+            // Macros and pseudo-instructions are hard to support in general:
+            // we don't know how much space they'll expand to before running
+            // the assembler, but we need that information to construct the
+            // dummy C code. When `two_pass_macros` is enabled, lines we can't
+            // size statically are deferred and resolved in `finish` by
+            // assembling the block once and reading back the real section
+            // sizes; otherwise a non-.text line is a hard error and a .text
+            // line counts as one 4-byte instruction, as it always was.
             if self.cur_section != Section::Text {
-                return Err(anyhow::anyhow!(format!(
-                    "instruction or macro call in non-.text section? not supported\n{}",
-                    real_line
-                )));
+                if !self.two_pass_macros {
+                    return Err(anyhow::anyhow!(format!(
+                        "instruction or macro call in non-.text section? not supported\n{}",
+                        real_line
+                    )));
+                }
+                self.unresolved_lines
+                    .push((self.cur_section, self.num_lines - 1));
+            } else {
+                self.add_sized(4, &real_line)?;
+                if self.two_pass_macros {
+                    let mnemonic = line.split_whitespace().next().unwrap_or("");
+                    if !FIXED_SIZE_MNEMONICS.contains(&mnemonic) {
+                        self.unresolved_lines
+                            .push((self.cur_section, self.num_lines - 1));
+                    }
+                }
             }
-            self.add_sized(4, &real_line)?;
         }
 
         if self.cur_section == Section::LateRodata {
@@ -383,20 +728,128 @@ impl GlobalAsmBlock {
 
     const MAX_FN_SIZE: usize = 100;
 
-    pub fn finish(&self, state: &mut GlobalState) -> Result<(Vec<String>, Function)> {
+    /// Assembles `asm_conts`/`late_rodata_asm_conts` once and reads back each
+    /// section's real size, for the handful of lines whose size couldn't be
+    /// determined just by parsing the source.
+    fn measure_section_sizes(
+        &self,
+        assembler: &str,
+        output_enc: &Encoding,
+        target: Target,
+    ) -> Result<HashMap<String, usize>> {
+        let temp_dir = TempDir::with_prefix("asm_processor_probe")?;
+        let s_file_path = temp_dir.path().join("probe.s");
+        let o_file_path = temp_dir.path().join("probe.o");
+
+        {
+            let mut s_file = File::create(&s_file_path)?;
+            for line in &self.asm_conts {
+                s_file.write_all(&output_enc.encode(line)?)?;
+                s_file.write_all(b"\n")?;
+            }
+            if !self.late_rodata_asm_conts.is_empty() {
+                s_file.write_all(b".section .late_rodata\n")?;
+                for line in &self.late_rodata_asm_conts {
+                    s_file.write_all(&output_enc.encode(line)?)?;
+                    s_file.write_all(b"\n")?;
+                }
+            }
+        }
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                "{} {} -o {}",
+                assembler,
+                shlex::try_quote(s_file_path.to_str().unwrap()).unwrap(),
+                shlex::try_quote(o_file_path.to_str().unwrap()).unwrap(),
+            ))
+            .status()?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "failed to measure the size of a macro/pseudo-instruction in {}: assembler invocation failed",
+                self.fn_desc
+            ));
+        }
+
+        section_sizes(&fs::read(&o_file_path)?, target)
+    }
+
+    /// Prefixes `prologue` with `static` when this block came from an
+    /// `INCLUDE_ASM_STATIC`/`GLOBAL_ASM_STATIC` macro, so the dummy C
+    /// function the real compiler sees isn't externally visible.
+    fn with_static(&self, prologue: String) -> String {
+        if self.is_static {
+            format!("static {}", prologue)
+        } else {
+            prologue
+        }
+    }
+
+    pub fn finish(
+        &self,
+        state: &mut GlobalState,
+        arch: &dyn Arch,
+        assembler: &str,
+        output_enc: &Encoding,
+    ) -> Result<(Vec<String>, Function)> {
         let mut src = vec!["".to_owned(); self.num_lines + 1];
         let mut late_rodata_dummy_bytes = vec![];
         let mut jtbl_rodata_size = 0;
         let mut late_rodata_fn_output = vec![];
 
-        let num_instr = self.fn_section_sizes[&Section::Text] / 4;
+        let mut fn_section_sizes = self.fn_section_sizes.clone();
+        let mut fn_ins_inds = self.fn_ins_inds.clone();
+
+        if !self.unresolved_lines.is_empty() {
+            let measured = self.measure_section_sizes(assembler, output_enc, arch.target())?;
+            for name in &self.section_names {
+                if let Some(&measured_size) = measured.get(name.as_str()) {
+                    fn_section_sizes.insert(name.clone(), measured_size);
+                }
+            }
 
-        if self.fn_section_sizes[&Section::LateRodata] > 0 {
+            if fn_section_sizes[".text"] != self.fn_section_sizes[".text"] {
+                // A .text macro expanded to a different instruction count
+                // than our static guess of one 4-byte instruction per line;
+                // redistribute fn_ins_inds proportionally from the measured
+                // total rather than trusting the guess.
+                let guessed_instrs: usize = fn_ins_inds.iter().map(|&(_, n)| n).sum();
+                let measured_instrs = fn_section_sizes[".text"] / 4;
+                if guessed_instrs > 0 {
+                    let scale = measured_instrs as f64 / guessed_instrs as f64;
+                    fn_ins_inds = fn_ins_inds
+                        .iter()
+                        .map(|&(line, n)| (line, ((n as f64) * scale).round() as usize))
+                        .collect();
+                    // Rounding can drift the total off by a little; put any
+                    // remaining slack on the last instruction-producing line,
+                    // or take any excess off the last lines.
+                    let scaled_total: usize = fn_ins_inds.iter().map(|&(_, n)| n).sum();
+                    if scaled_total <= measured_instrs {
+                        if let Some(last) = fn_ins_inds.last_mut() {
+                            last.1 += measured_instrs - scaled_total;
+                        }
+                    } else {
+                        let mut excess = scaled_total - measured_instrs;
+                        for entry in fn_ins_inds.iter_mut().rev() {
+                            let take = entry.1.min(excess);
+                            entry.1 -= take;
+                            excess -= take;
+                        }
+                    }
+                }
+            }
+        }
+
+        let num_instr = fn_section_sizes[".text"] / 4;
+
+        if fn_section_sizes[".late_rodata"] > 0 {
             // Generate late rodata by emitting unique float constants.
             // This requires 3 instructions for each 4 bytes of rodata.
             // If we know alignment, we can use doubles, which give 3
             // instructions for 8 bytes of rodata.
-            let size = self.fn_section_sizes[&Section::LateRodata] / 4;
+            let size = fn_section_sizes[".late_rodata"] / 4;
             let mut skip_next = false;
             let mut needs_double = self.late_rodata_alignment != 0;
             let mut extra_mips1_nop = false;
@@ -490,12 +943,12 @@ impl GlobalAsmBlock {
         }
 
         let mut text_name = None;
-        if self.fn_section_sizes[&Section::Text] > 0 || !late_rodata_fn_output.is_empty() {
+        if fn_section_sizes[".text"] > 0 || !late_rodata_fn_output.is_empty() {
             let new_name = state.make_name("func");
-            src[0] = state.func_prologue(&new_name);
+            src[0] = self.with_static(state.func_prologue(&new_name));
             text_name = Some(new_name);
             src[self.num_lines] = state.func_epilogue();
-            let instr_count = self.fn_section_sizes[&Section::Text] / 4;
+            let instr_count = fn_section_sizes[".text"] / 4;
             if instr_count < state.min_instr_count {
                 return Err(anyhow::anyhow!(format!("too short .text block",)));
             }
@@ -507,7 +960,7 @@ impl GlobalAsmBlock {
             let mut rodata_stack: Vec<String> = late_rodata_fn_output.clone();
             rodata_stack.reverse();
 
-            for (line, count) in &self.fn_ins_inds {
+            for (line, count) in &fn_ins_inds {
                 for _ in 0..*count {
                     if fn_emitted > Self::MAX_FN_SIZE
                         && instr_count - tot_emitted > state.min_instr_count
@@ -525,7 +978,7 @@ impl GlobalAsmBlock {
                         src[*line] += format!(
                             " {} {} ",
                             state.func_epilogue(),
-                            state.func_prologue(&large_func_name)
+                            self.with_static(state.func_prologue(&large_func_name))
                         )
                         .as_str();
                     }
@@ -569,7 +1022,7 @@ impl GlobalAsmBlock {
         }
 
         let mut rodata_name = None;
-        if self.fn_section_sizes[&Section::Rodata] > 0 {
+        if fn_section_sizes[".rodata"] > 0 {
             if state.pascal {
                 return Err(anyhow::anyhow!(format!(
                     ".rodata isn't supported with Pascal for now"
@@ -579,26 +1032,26 @@ impl GlobalAsmBlock {
             src[self.num_lines] += format!(
                 " const char {}[{}] = {{1}};",
                 new_name,
-                self.fn_section_sizes[&Section::Rodata]
+                fn_section_sizes[".rodata"]
             )
             .as_str();
             rodata_name = Some(new_name);
         }
 
         let mut data_name = None;
-        if self.fn_section_sizes[&Section::Data] > 0 {
+        if fn_section_sizes[".data"] > 0 {
             let new_name = state.make_name("data");
             let line = if state.pascal {
                 format!(
                     " var {}: packed array[1..{}] of char := [otherwise: 0];",
                     new_name,
-                    self.fn_section_sizes[&Section::Data]
+                    fn_section_sizes[".data"]
                 )
             } else {
                 format!(
                     " char {}[{}] = {{1}};",
                     new_name,
-                    self.fn_section_sizes[&Section::Data]
+                    fn_section_sizes[".data"]
                 )
             };
             src[self.num_lines] += line.as_str();
@@ -606,22 +1059,70 @@ impl GlobalAsmBlock {
         }
 
         let mut bss_name = None;
-        if self.fn_section_sizes[&Section::Bss] > 0 {
+        if fn_section_sizes[".bss"] > 0 {
             let new_name = state.make_name("bss");
             if state.pascal {
                 return Err(anyhow::anyhow!(format!(
                     ".bss isn't supported with Pascal for now"
                 )));
             }
-            src[self.num_lines] += format!(
-                " char {}[{}];",
-                new_name,
-                self.fn_section_sizes[&Section::Bss]
-            )
-            .as_str();
+            src[self.num_lines] += format!(" char {}[{}];", new_name, fn_section_sizes[".bss"])
+                .as_str();
             bss_name = Some(new_name);
         }
 
+        let mut data = HashMap::from([
+            (
+                ".text".to_string(),
+                (text_name, fn_section_sizes[".text"]),
+            ),
+            (
+                ".data".to_string(),
+                (data_name, fn_section_sizes[".data"]),
+            ),
+            (
+                ".rodata".to_string(),
+                (rodata_name, fn_section_sizes[".rodata"]),
+            ),
+            (".bss".to_string(), (bss_name, fn_section_sizes[".bss"])),
+        ]);
+
+        // Additional named sections discovered via `.section` directives
+        // (e.g. `.rodata.str1.4`, `-ffunction-sections`/`-fdata-sections`
+        // output) don't fit into the four canonical buckets above, so each
+        // gets its own dummy global, annotated to land in the right place.
+        for name in &self.section_names {
+            if CANONICAL_SECTION_NAMES.contains(&name.as_str()) {
+                continue;
+            }
+            let size = fn_section_sizes[name.as_str()];
+            if size == 0 {
+                continue;
+            }
+            let kind = self.section_kinds[name];
+            if state.pascal && matches!(kind, Section::Rodata | Section::Bss | Section::Sbss) {
+                return Err(anyhow::anyhow!(format!(
+                    "{} isn't supported with Pascal for now",
+                    name
+                )));
+            }
+            let new_name = state.make_name(match kind {
+                Section::Rodata => "rodata",
+                Section::Bss | Section::Sbss => "bss",
+                _ => "data",
+            });
+            let line = if state.pascal {
+                format!(
+                    " var {}: packed array[1..{}] of char := [otherwise: 0];",
+                    new_name, size
+                )
+            } else {
+                format!(" {}", arch.reserve_section(name, kind, size, &new_name))
+            };
+            src[self.num_lines] += line.as_str();
+            data.insert(name.clone(), (Some(new_name), size));
+        }
+
         let ret_fn = Function {
             text_glabels: self.text_glabels.clone(),
             asm_conts: self.asm_conts.clone(),
@@ -629,36 +1130,34 @@ impl GlobalAsmBlock {
             jtbl_rodata_size,
             late_rodata_asm_conts: self.late_rodata_asm_conts.clone(),
             fn_desc: self.fn_desc.clone(),
-            data: HashMap::from([
-                (
-                    ".text".to_string(),
-                    (text_name, self.fn_section_sizes[&Section::Text]),
-                ),
-                (
-                    ".data".to_string(),
-                    (data_name, self.fn_section_sizes[&Section::Data]),
-                ),
-                (
-                    ".rodata".to_string(),
-                    (rodata_name, self.fn_section_sizes[&Section::Rodata]),
-                ),
-                (
-                    ".bss".to_string(),
-                    (bss_name, self.fn_section_sizes[&Section::Bss]),
-                ),
-            ]),
+            data,
+            is_static: self.is_static,
         };
 
         Ok((src, ret_fn))
     }
 }
 
-/// Convert a float string to its hexadecimal representation
-fn repl_float_hex(cap: &regex::Captures) -> String {
-    let float_str = cap[0].trim().trim_end_matches('f');
-    let float_val = float_str.parse::<f32>().unwrap();
-    let hex_val = f32::to_be_bytes(float_val);
-    format!("{}", u32::from_be_bytes(hex_val))
+/// Convert a float literal to its hexadecimal representation: a single
+/// decimal word for an `f`-suffixed single-precision literal, or two decimal
+/// words (ordered per `big_endian`) for a bare double-precision one.
+fn repl_float_hex(cap: &regex::Captures, big_endian: bool) -> String {
+    let raw = cap[0].trim();
+    if let Some(float_str) = raw.strip_suffix('f') {
+        let float_val = float_str.parse::<f32>().unwrap();
+        let hex_val = f32::to_be_bytes(float_val);
+        format!("{}", u32::from_be_bytes(hex_val))
+    } else {
+        let float_val = raw.parse::<f64>().unwrap();
+        let hex_val = f64::to_be_bytes(float_val);
+        let hi_word = u32::from_be_bytes(hex_val[0..4].try_into().unwrap());
+        let lo_word = u32::from_be_bytes(hex_val[4..8].try_into().unwrap());
+        if big_endian {
+            format!("{}, {}", hi_word, lo_word)
+        } else {
+            format!("{}, {}", lo_word, hi_word)
+        }
+    }
 }
 
 pub(crate) fn parse_source(
@@ -666,45 +1165,17 @@ pub(crate) fn parse_source(
     args: &AsmProcArgs,
     encode: bool,
 ) -> Result<RunResult> {
-    let (mut min_instr_count, mut skip_instr_count) = match (args.opt.clone(), args.g3) {
-        (OptLevel::O0, false) => match args.framepointer {
-            true => (8, 8),
-            false => (4, 4),
-        },
-        (OptLevel::O1, false) | (OptLevel::O2, false) => match args.framepointer {
-            true => (6, 5),
-            false => (2, 1),
-        },
-        (OptLevel::G, false) => match args.framepointer {
-            true => (7, 7),
-            false => (4, 4),
-        },
-        (OptLevel::O2, true) => match args.framepointer {
-            true => (4, 4),
-            false => (2, 2),
-        },
-        _ => {
-            return Err(anyhow::anyhow!(
-                "Unsupported optimization level: -g3 can only be used with -O2"
-            ))
-            .context("Invalid arguments")
-        }
-    };
-
-    let mut prelude_if_late_rodata = 0;
-    if args.kpic {
-        // Without optimizations, the PIC prelude always takes up 3 instructions.
-        // With optimizations, the prelude is optimized out if there's no late rodata.
-        if args.opt == OptLevel::O2 || args.g3 {
-            prelude_if_late_rodata = 3;
-        } else {
-            min_instr_count += 3;
-            skip_instr_count += 3;
-        }
-    }
-
-    let use_jtbl_for_rodata =
-        (args.opt == OptLevel::O2 || args.g3) && !args.framepointer && !args.kpic;
+    let arch = select_arch(args.arch);
+    let (min_instr_count, skip_instr_count, prelude_if_late_rodata) = arch.instr_counts(args)?;
+    // `--min-instr-count`/`--skip-instr-count`/`--prelude-if-late-rodata` let a
+    // user calibrate these by hand for a compiler this tool doesn't have a
+    // built-in table for, instead of requiring a new Arch match arm.
+    let min_instr_count = args.min_instr_count.unwrap_or(min_instr_count);
+    let skip_instr_count = args.skip_instr_count.unwrap_or(skip_instr_count);
+    let prelude_if_late_rodata = args
+        .prelude_if_late_rodata
+        .unwrap_or(prelude_if_late_rodata);
+    let use_jtbl_for_rodata = arch.use_jtbl_for_rodata(args);
 
     let mut state = GlobalState::new(
         min_instr_count,
@@ -720,11 +1191,26 @@ pub(crate) fn parse_source(
     let mut output_lines: Vec<String> = vec![format!("#line 1 \"{}\"", infile_path.display())];
     let mut deps: Vec<String> = vec![];
 
-    let mut is_cutscene_data = false;
+    let mut is_float_array_data = false;
     let mut is_early_include = false;
 
-    let cutscene_re = Regex::new(r"CutsceneData (.|\n)*\[\] = \{")?;
-    let float_re = Regex::new(r"[-+]?[0-9]*\.?[0-9]+([eE][-+]?[0-9]+)?f")?;
+    // `--encode-float-arrays TYPE` marks struct/array types whose float
+    // literals should be rewritten to their IEEE-754 word(s) inline, so that
+    // a matching/non-matching compiler can't format the same constant
+    // differently. This used to be hardcoded to a single type, `CutsceneData`.
+    let float_array_res = args
+        .encode_float_arrays
+        .iter()
+        .map(|ty| Regex::new(&format!(r"{} (.|\n)*\[\] = \{{", regex::escape(ty))))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    // A bare integer (no `.`, no exponent, no `f` suffix) must NOT match:
+    // encoded arrays like `CutsceneData` mix integer and floating-point
+    // fields, and an integer literal fed into `repl_float_hex` would be
+    // silently reinterpreted as an `f64` and corrupted.
+    let float_re = Regex::new(
+        r"[-+]?[0-9]*\.[0-9]+([eE][-+]?[0-9]+)?f?|[-+]?[0-9]+[eE][-+]?[0-9]+f?|[-+]?[0-9]+f",
+    )?;
+    let big_endian = arch.is_big_endian();
 
     for (line_no, line) in read_to_string(infile_path)?.lines().enumerate() {
         let line_no = line_no + 1;
@@ -738,7 +1224,7 @@ pub(crate) fn parse_source(
 
         if let Some((ref mut gasm, start_index)) = global_asm {
             if line.starts_with(')') {
-                let (src, fun) = gasm.finish(&mut state)?;
+                let (src, fun) = gasm.finish(&mut state, arch.as_ref(), &args.assembler, output_enc)?;
                 for (i, line2) in src.iter().enumerate() {
                     output_lines[start_index + i] = line2.clone();
                 }
@@ -747,17 +1233,29 @@ pub(crate) fn parse_source(
             } else {
                 gasm.process_line(&raw_line, output_enc)?;
             }
-        } else if line == "GLOBAL_ASM(" || line == "#pragma GLOBAL_ASM(" {
+        } else if line == "GLOBAL_ASM(" || line == "#pragma GLOBAL_ASM(" || line == "GLOBAL_ASM_STATIC(" {
             global_asm = Some((
-                GlobalAsmBlock::new(format!("GLOBAL_ASM block at line {}", &line_no.to_string())),
+                GlobalAsmBlock::new(
+                    format!("GLOBAL_ASM block at line {}", &line_no.to_string()),
+                    args.two_pass_macros,
+                    line == "GLOBAL_ASM_STATIC(",
+                ),
                 output_lines.len(),
             ));
-        } else if ((line.starts_with("GLOBAL_ASM(\"") || line.starts_with("#pragma GLOBAL_ASM(\""))
+        } else if ((line.starts_with("GLOBAL_ASM(\"")
+            || line.starts_with("#pragma GLOBAL_ASM(\"")
+            || line.starts_with("GLOBAL_ASM_STATIC(\""))
             && line.ends_with("\")"))
-            || ((line.starts_with("INCLUDE_ASM(\"") || line.starts_with("INCLUDE_RODATA(\""))
+            || ((line.starts_with("INCLUDE_ASM(\"")
+                || line.starts_with("INCLUDE_RODATA(\"")
+                || line.starts_with("INCLUDE_ASM_STATIC(\"")
+                || line.starts_with("INCLUDE_RODATA_STATIC(\""))
                 && line.contains("\",")
                 && line.ends_with(");"))
         {
+            let is_static = line.starts_with("INCLUDE_ASM_STATIC(")
+                || line.starts_with("INCLUDE_RODATA_STATIC(")
+                || line.starts_with("GLOBAL_ASM_STATIC(");
             let (prologue, fname) = if line.starts_with("INCLUDE_") {
                 // INCLUDE_ASM("path/to", functionname);
                 let (before, after) = line.split_once("\",").unwrap();
@@ -778,7 +1276,7 @@ pub(crate) fn parse_source(
                 (vec![], fname)
             };
 
-            let mut gasm = GlobalAsmBlock::new(fname.clone());
+            let mut gasm = GlobalAsmBlock::new(fname.clone(), args.two_pass_macros, is_static);
             for line2 in prologue {
                 gasm.process_line(line2.trim_end(), output_enc)?;
             }
@@ -798,7 +1296,7 @@ pub(crate) fn parse_source(
                 gasm.process_line(line2.trim_end(), output_enc)?;
             }
 
-            let (src, fun) = gasm.finish(&mut state)?;
+            let (src, fun) = gasm.finish(&mut state, arch.as_ref(), &args.assembler, output_enc)?;
             let output_lines_len = output_lines.len();
             output_lines[output_lines_len - 1] = src.join("");
             asm_functions.push(fun);
@@ -832,17 +1330,23 @@ pub(crate) fn parse_source(
             let output_lines_len = output_lines.len();
             output_lines[output_lines_len - 1] = res_str;
         } else {
-            if args.encode_cutscene_data_float_encoding {
-                // This is a hack to replace all floating-point numbers in an array of a particular type
-                // (in this case CutsceneData) with their corresponding IEEE-754 hexadecimal representation
-                if cutscene_re.is_match(line) {
-                    is_cutscene_data = true;
+            if !float_array_res.is_empty() {
+                // Replace all floating-point numbers in an array of one of
+                // the user-specified types with their corresponding
+                // IEEE-754 word(s), so a matching/non-matching compiler
+                // can't format the same constant differently.
+                if float_array_res.iter().any(|re| re.is_match(line)) {
+                    is_float_array_data = true;
                 } else if line.ends_with("};") {
-                    is_cutscene_data = false;
+                    is_float_array_data = false;
                 }
 
-                if is_cutscene_data {
-                    raw_line = float_re.replace_all(&raw_line, repl_float_hex).into_owned();
+                if is_float_array_data {
+                    raw_line = float_re
+                        .replace_all(&raw_line, |caps: &regex::Captures| {
+                            repl_float_hex(caps, big_endian)
+                        })
+                        .into_owned();
                 }
             }
             let output_lines_len = output_lines.len();